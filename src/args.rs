@@ -1,15 +1,72 @@
-use anyhow::{Context, Result};
-use clap::{Parser, ValueEnum};
-use css_color_parser::Color as CssColor;
+use std::env;
+use std::ffi::OsString;
 
-#[cfg(feature = "i3")]
-use font_loader::system_fonts;
+use anyhow::{bail, Context, Result};
+use clap::{CommandFactory, Parser, ValueEnum};
+use css_color_parser::Color as CssColor;
+use serde::Serialize;
 
-#[cfg(feature = "i3")]
-use log::{info, warn};
+use pangocairo::pango;
 
+use crate::config;
+use crate::filter::WindowFilter;
+use crate::style::StyleRule;
 use crate::utils;
 
+#[derive(ValueEnum, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    I3,
+    Hyprland,
+    Sway,
+}
+
+impl Backend {
+    /// Name of the cargo feature that enables this backend.
+    pub fn feature_name(&self) -> &'static str {
+        match self {
+            Backend::I3 => "i3",
+            Backend::Hyprland => "hyprland",
+            Backend::Sway => "sway",
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Target {
+    Windows,
+    Workspaces,
+    Outputs,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OutputFormat {
+    Id,
+    Json,
+}
+
+/// Which windows get the shortest hints.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HintPriority {
+    /// Windows from the top left to the bottom right
+    Position,
+    /// Windows closest to the focused window first
+    Distance,
+    /// Most recently used windows first, starting with the previously focused one
+    Mru,
+}
+
+/// What to show next to the hint.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Label {
+    /// Window title
+    Title,
+    /// Window class or app_id
+    Class,
+    /// Window class followed by the title
+    Both,
+}
+
 #[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum HorizontalAlign {
     Left,
@@ -24,60 +81,30 @@ pub enum VerticalAlign {
     Bottom,
 }
 
-/// Load a system font.
-#[cfg(feature = "i3")]
-fn load_font(font_family: &str) -> Result<Vec<u8>> {
-    let mut font_family_property = system_fonts::FontPropertyBuilder::new()
-        .family(font_family)
-        .build();
-    let info = system_fonts::query_specific(&mut font_family_property);
-    info!("Returned effective font is: {:?}", info);
-    let (loaded_font, _) =
-        if let Some((loaded_font, index)) = system_fonts::get(&font_family_property) {
-            (loaded_font, index)
-        } else {
-            warn!("Family not found, falling back to first Monospace font");
-            let mut font_monospace_property =
-                system_fonts::FontPropertyBuilder::new().monospace().build();
-            let sysfonts = system_fonts::query_specific(&mut font_monospace_property);
-            warn!("Falling back to font '{font}'", font = sysfonts[0]);
-            let (loaded_font, index) = system_fonts::get(&font_monospace_property)
-                .context("Couldn't find suitable font")?;
-            (loaded_font, index)
-        };
-    Ok(loaded_font)
-}
-
 /// Generate a valid `FontConfig` from `f`.
-/// `f` is expected to be in format `Mono:72`.
-fn parse_truetype_font(f: &str) -> Result<FontConfig> {
-    let mut v = f.split(':');
-    let (family, size) = (
-        v.next().context("Wrong font format")?,
-        v.next().context("Wrong font format")?,
-    );
-
-    // Skip font loading for Hyprland/Wayland (uses Cairo default fonts)
-    #[cfg(feature = "hyprland")]
-    {
-        let font_config = FontConfig {
-            font_family: family.to_string(),
+///
+/// `f` is either a Pango font description such as `JetBrains Mono Bold 28` or in the older format
+/// `Mono:72`. Sizes are in pixels.
+fn parse_font(f: &str) -> Result<FontConfig> {
+    if let Some((family, size)) = f.rsplit_once(':') {
+        let mut description = pango::FontDescription::new();
+        description.set_family(family);
+        return Ok(FontConfig {
+            description,
             font_size: size.parse::<f64>().context("Couldn't parse font size")?,
-            loaded_font: Vec::new(),
-        };
-        return Ok(font_config);
+        });
     }
 
-    #[cfg(not(feature = "hyprland"))]
-    {
-        let loaded_font = load_font(family).context("Couldn't load font")?;
-        let font_config = FontConfig {
-            font_family: family.to_string(),
-            font_size: size.parse::<f64>().context("Couldn't parse font size")?,
-            loaded_font,
-        };
-        Ok(font_config)
+    let mut description = pango::FontDescription::from_string(f);
+    let font_size = f64::from(description.size()) / f64::from(pango::SCALE);
+    if font_size <= 0.0 {
+        bail!("Font description '{f}' is missing a size");
     }
+    description.unset_fields(pango::FontMask::SIZE);
+    Ok(FontConfig {
+        description,
+        font_size,
+    })
 }
 
 /// Validate coordinates and parse offset.
@@ -99,7 +126,7 @@ fn parse_offset(c: &str) -> Result<Offset, String> {
 }
 
 /// Parse a color into a tuple of floats.
-fn parse_color(color_str: &str) -> Result<(f64, f64, f64, f64), String> {
+pub fn parse_color(color_str: &str) -> Result<(f64, f64, f64, f64), String> {
     let color = color_str
         .parse::<CssColor>()
         .map_err(|_| "Invalid color format")?;
@@ -113,18 +140,15 @@ fn parse_color(color_str: &str) -> Result<(f64, f64, f64, f64), String> {
 
 #[derive(Debug, Clone)]
 pub struct Offset {
-    #[allow(dead_code)]
     pub x: i32,
-    #[allow(dead_code)]
     pub y: i32,
 }
 
 #[derive(Debug, Clone)]
 pub struct FontConfig {
-    pub font_family: String,
+    /// Family, weight and style of the font, the size is kept in `font_size`.
+    pub description: pango::FontDescription,
     pub font_size: f64,
-    #[allow(dead_code)]
-    pub loaded_font: Vec<u8>,
 }
 
 fn parse_exit_keys(s: &str) -> Result<utils::Sequence> {
@@ -132,15 +156,18 @@ fn parse_exit_keys(s: &str) -> Result<utils::Sequence> {
 }
 
 #[derive(Parser, Debug)]
-#[command(name = "wmfocus", author, about, version)]
+#[command(
+    name = "wmfocus",
+    author,
+    about,
+    version,
+    after_help = "Exits with 0 if a window was selected, 1 on errors, 2 for invalid arguments, 3 if \
+                  cancelled and 4 if there was nothing to select."
+)]
 pub struct AppConfig {
-    /// Use a specific TrueType font with this format: family:size
-    #[arg(
-        short,
-        long,
-        default_value = "Mono:72",
-        value_parser(parse_truetype_font)
-    )]
+    /// Use a specific font, either as a Pango font description like "JetBrains Mono Bold 28" or
+    /// with this format: family:size
+    #[arg(short, long, default_value = "Mono:72", value_parser(parse_font))]
     pub font: FontConfig,
 
     /// Define a set of possbile values to use as hint characters
@@ -205,6 +232,26 @@ pub struct AppConfig {
     )]
     pub bg_color_current: (f64, f64, f64, f64),
 
+    /// Background color urgent window (CSS notation)
+    #[arg(
+        long = "bgcolorurgent",
+        display_order = 55,
+        default_value = "rgba(200, 40, 40, 0.9)",
+        value_parser(parse_color)
+    )]
+    pub bg_color_urgent: (f64, f64, f64, f64),
+
+    /// Darken the desktop with this color (CSS notation) and outline the windows
+    #[arg(
+        long,
+        display_order = 56,
+        value_name = "COLOR",
+        num_args = 0..=1,
+        default_missing_value = "rgba(0, 0, 0, 0.6)",
+        value_parser(parse_color)
+    )]
+    pub dim: Option<(f64, f64, f64, f64)>,
+
     /// Horizontal alignment of the box inside the window
     #[arg(
         long = "halign",
@@ -231,6 +278,14 @@ pub struct AppConfig {
     #[arg(short, long)]
     pub print_only: bool,
 
+    /// Also print class, workspace, PID and title of the window when using --print-only
+    #[arg(long, requires = "print_only", conflicts_with = "output")]
+    pub print_details: bool,
+
+    /// Print the selected window in this format but don't change focus (implies --print-only)
+    #[arg(long, ignore_case = true)]
+    pub output: Option<OutputFormat>,
+
     /// Offset box from edge of window relative to alignment (x,y)
     #[arg(
         short,
@@ -248,13 +303,119 @@ pub struct AppConfig {
     /// If this flag is set, the currently active window will swap with the selected window.
     #[arg(short, long)]
     pub swap: bool,
+
+    /// Window manager backend to use (detected from the environment by default)
+    #[arg(long, ignore_case = true)]
+    pub backend: Option<Backend>,
+
+    /// Only show windows matching any of these criteria: class=REGEX, title=REGEX, workspace=NAME,
+    /// output=NAME, floating, tiled, focused or urgent
+    #[arg(long, value_name = "FILTER")]
+    pub include: Vec<WindowFilter>,
+
+    /// Hide windows matching any of these criteria (same format as --include)
+    #[arg(long, value_name = "FILTER")]
+    pub exclude: Vec<WindowFilter>,
+
+    /// What to put hints on
+    #[arg(long, default_value = "windows", ignore_case = true)]
+    pub target: Target,
+
+    /// Move the focused window to the selected output instead of focusing it (only for --target
+    /// outputs)
+    #[arg(long = "move", conflicts_with_all(&["swap", "print_only"]))]
+    pub move_window: bool,
+
+    /// Also show windows on hidden workspaces and in the scratchpad, listed in a grid
+    #[arg(short, long)]
+    pub all_workspaces: bool,
+
+    /// Which windows get the shortest hints
+    #[arg(long, default_value = "position", ignore_case = true)]
+    pub hint_priority: HintPriority,
+
+    /// Focus the previously focused window right away without showing any hints
+    #[arg(long)]
+    pub previous: bool,
+
+    /// Focus the urgent window right away without showing any hints, show hints as usual if no
+    /// window is urgent
+    #[arg(long, conflicts_with = "previous")]
+    pub urgent: bool,
+
+    /// Keep running and record which windows get focus or become urgent, for --previous, --urgent
+    /// and --hint-priority mru
+    #[arg(long, conflicts_with_all(&["previous", "urgent", "print_only"]))]
+    pub track: bool,
+
+    /// Show a label next to the hint, truncated to the width of the window
+    #[arg(long, ignore_case = true)]
+    pub label: Option<Label>,
+
+    /// Show the application icon from the icon theme next to the hint
+    #[arg(long)]
+    pub icons: bool,
+
+    /// Icon theme for --icons (defaults to the GTK icon theme, falling back to hicolor)
+    #[arg(long, value_name = "THEME")]
+    pub icon_theme: Option<String>,
+
+    /// Use the settings of this profile from the config file
+    #[arg(long)]
+    pub profile: Option<String>,
+
+    /// Ignore the config file
+    #[arg(long, conflicts_with = "profile")]
+    pub no_config: bool,
+
+    /// Styling rules from the config file.
+    #[arg(skip)]
+    pub styles: Vec<StyleRule>,
 }
 
-pub fn parse_args() -> AppConfig {
-    let mut config = AppConfig::parse();
+/// Parse the command line on top of the settings from the config file.
+pub fn parse_args() -> Result<AppConfig> {
+    let command = AppConfig::command();
+    let matches = command.clone().get_matches();
+    let profile = matches.get_one::<String>("profile").map(String::as_str);
+    let file_config = match config::config_path() {
+        _ if matches.get_flag("no_config") => config::FileConfig::default(),
+        Some(path) => config::load(&path, profile, &command, &matches)?,
+        None if profile.is_some() => bail!("Couldn't find the config file for --profile"),
+        None => config::FileConfig::default(),
+    };
+
+    let mut args: Vec<OsString> = env::args_os().take(1).collect();
+    args.extend(file_config.args);
+    args.extend(env::args_os().skip(1));
+    let mut config = AppConfig::parse_from(args);
+    config.styles = file_config.styles;
     if config.fill {
         config.horizontal_align = HorizontalAlign::Center;
         config.vertical_align = VerticalAlign::Center;
     }
-    config
+    if config.output.is_some() {
+        config.print_only = true;
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_font() {
+        let font = parse_font("Mono:72").unwrap();
+        assert_eq!(font.description.family().as_deref(), Some("Mono"));
+        assert_eq!(font.font_size, 72.0);
+
+        let font = parse_font("JetBrains Mono Bold 28").unwrap();
+        assert_eq!(font.description.family().as_deref(), Some("JetBrains Mono"));
+        assert_eq!(font.description.weight(), pango::Weight::Bold);
+        assert_eq!(font.font_size, 28.0);
+
+        assert!(parse_font("Mono").is_err());
+        assert!(parse_font("Mono:big").is_err());
+    }
 }
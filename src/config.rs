@@ -0,0 +1,397 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use log::debug;
+
+use crate::style::StyleRule;
+
+/// Table of named profiles in the config file.
+const PROFILES: &str = "profile";
+
+/// Array of styling rules in the config file and in profiles.
+const STYLES: &str = "style";
+
+/// Options that only make sense on the command line.
+const CLI_ONLY: [&str; 4] = ["help", "version", "profile", "no-config"];
+
+/// Location of the config file, `$XDG_CONFIG_HOME/wmfocus/config.toml`.
+pub fn config_path() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+        .map(|d| d.join("wmfocus/config.toml"))
+}
+
+/// Settings from the config file.
+#[derive(Debug, Default)]
+pub struct FileConfig {
+    /// Command line arguments for the options set in the file.
+    pub args: Vec<OsString>,
+    /// `[[style]]` rules, the ones of the selected profile come last.
+    pub styles: Vec<StyleRule>,
+}
+
+/// Load the config file at `path`.
+///
+/// Keys are the long names of the command line options. Top level keys apply to every run, the
+/// keys of `[profile.<profile>]` take precedence over them. Options that are already set in
+/// `matches` or that conflict with an option set there are skipped so that the command line always
+/// wins. Every value is checked with the
+/// parser of its option so that mistakes are reported with the file and key they're in.
+pub fn load(
+    path: &Path,
+    profile: Option<&str>,
+    command: &Command,
+    matches: &ArgMatches,
+) -> Result<FileConfig> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            if let Some(profile) = profile {
+                bail!(
+                    "Profile '{profile}' not found, {} doesn't exist",
+                    path.display()
+                );
+            }
+            return Ok(FileConfig::default());
+        }
+        Err(e) => return Err(e).with_context(|| format!("Couldn't read {}", path.display())),
+    };
+    debug!("Loading config from {}", path.display());
+    let mut table: toml::Table =
+        toml::from_str(&contents).with_context(|| format!("Couldn't parse {}", path.display()))?;
+
+    let profiles = match table.remove(PROFILES) {
+        Some(toml::Value::Table(profiles)) => profiles,
+        Some(_) => bail!("{}: '{PROFILES}' must be a table", path.display()),
+        None => toml::Table::new(),
+    };
+    let mut styles = match table.remove(STYLES) {
+        Some(value) => {
+            style_rules(&value, STYLES).map_err(|e| e.context(path.display().to_string()))?
+        }
+        None => vec![],
+    };
+    let mut settings: BTreeMap<String, (String, toml::Value)> = table
+        .into_iter()
+        .map(|(key, value)| (key.clone(), (key, value)))
+        .collect();
+    if let Some(profile) = profile {
+        let Some(toml::Value::Table(overrides)) = profiles.get(profile) else {
+            bail!("{}: no table [{PROFILES}.{profile}]", path.display());
+        };
+        for (key, value) in overrides {
+            let location = format!("{PROFILES}.{profile}.{key}");
+            if key == STYLES {
+                styles.extend(
+                    style_rules(value, &location)
+                        .map_err(|e| e.context(path.display().to_string()))?,
+                );
+            } else {
+                // Drop top level keys that couldn't be combined with the profile's.
+                if let Some(arg) = find_arg(command, key) {
+                    settings.retain(|other, _| {
+                        find_arg(command, other).is_none_or(|o| !conflicts(command, arg, o))
+                    });
+                }
+                settings.insert(key.clone(), (location, value.clone()));
+            }
+        }
+    }
+
+    let mut args = vec![];
+    for (key, (location, value)) in settings {
+        let arg = find_arg(command, &key)
+            .with_context(|| format!("{}: unknown key '{location}'", path.display()))?;
+        let new_args = setting_args(command, arg, &key, &value)
+            .with_context(|| format!("{}: invalid value for '{location}'", path.display()))?;
+        let on_command_line =
+            |a: &Arg| matches.value_source(a.get_id().as_str()) == Some(ValueSource::CommandLine);
+        if on_command_line(arg) {
+            debug!("Ignoring '{location}' from the config file, it's set on the command line");
+            continue;
+        }
+        if let Some(other) = command
+            .get_arguments()
+            .find(|o| on_command_line(o) && conflicts(command, arg, o))
+        {
+            debug!(
+                "Ignoring '{location}' from the config file, it conflicts with --{}",
+                other.get_long().unwrap_or_default()
+            );
+            continue;
+        }
+        args.extend(new_args);
+    }
+    Ok(FileConfig { args, styles })
+}
+
+/// The option set by `key` in the config file.
+fn find_arg<'a>(command: &'a Command, key: &str) -> Option<&'a Arg> {
+    command
+        .get_arguments()
+        .find(|a| a.get_long() == Some(key) && !CLI_ONLY.contains(&key))
+}
+
+/// Whether `a` and `b` can't be used together, no matter which of them declares the conflict.
+fn conflicts(command: &Command, a: &Arg, b: &Arg) -> bool {
+    let declared = |x: &Arg, y: &Arg| {
+        command
+            .get_arg_conflicts_with(x)
+            .iter()
+            .any(|c| c.get_id() == y.get_id())
+    };
+    declared(a, b) || declared(b, a)
+}
+
+/// Parse the array of `[[style]]` tables at `location`.
+fn style_rules(value: &toml::Value, location: &str) -> Result<Vec<StyleRule>> {
+    let Some(tables) = value.as_array() else {
+        bail!("'{location}' must be an array of tables like [[{location}]]");
+    };
+    tables
+        .iter()
+        .enumerate()
+        .map(|(i, table)| {
+            let table = table
+                .as_table()
+                .with_context(|| format!("'{location}[{i}]' must be a table"))?;
+            StyleRule::from_table(table).with_context(|| format!("in '{location}[{i}]'"))
+        })
+        .collect()
+}
+
+/// Command line arguments for setting `arg` to `value`.
+fn setting_args(
+    command: &Command,
+    arg: &Arg,
+    key: &str,
+    value: &toml::Value,
+) -> Result<Vec<OsString>> {
+    if matches!(arg.get_action(), ArgAction::SetTrue) {
+        let toml::Value::Boolean(set) = value else {
+            bail!("expected true or false");
+        };
+        return Ok(if *set {
+            vec![format!("--{key}").into()]
+        } else {
+            vec![]
+        });
+    }
+
+    let values = match value {
+        toml::Value::Array(values) if matches!(arg.get_action(), ArgAction::Append) => {
+            values.iter().collect()
+        }
+        value => vec![value],
+    };
+    let mut args = vec![];
+    for value in values {
+        let value = match value {
+            toml::Value::String(s) => s.clone(),
+            toml::Value::Integer(i) => i.to_string(),
+            toml::Value::Float(f) => f.to_string(),
+            _ => bail!("expected a string or a number"),
+        };
+        args.push(format!("--{key}={value}").into());
+    }
+
+    // Run the same parsers as for the command line, such as `parse_color`.
+    if let Err(e) = command
+        .clone()
+        .no_binary_name(true)
+        .try_get_matches_from(&args)
+    {
+        match std::error::Error::source(&e) {
+            Some(source) => bail!("{source}"),
+            // Without a source, the first paragraph of clap's message is what went wrong.
+            None => {
+                let message = e.to_string();
+                let message = message.split("\n\n").next().unwrap_or_default();
+                bail!("{}", message.trim_start_matches("error: ").trim());
+            }
+        }
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, Parser};
+
+    use super::*;
+    use crate::args::AppConfig;
+
+    fn write_config(name: &str, contents: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("wmfocus-{name}-{}.toml", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn args_for(path: &Path, profile: Option<&str>, cli: &[&str]) -> Result<Vec<String>> {
+        let command = AppConfig::command();
+        let matches = command
+            .clone()
+            .try_get_matches_from(std::iter::once("wmfocus").chain(cli.iter().copied()))
+            .unwrap();
+        let args = load(path, profile, &command, &matches)?.args;
+        Ok(args.into_iter().map(|a| a.into_string().unwrap()).collect())
+    }
+
+    #[test]
+    fn test_file_args_with_profile() {
+        let path = write_config(
+            "profile",
+            r##"
+                font = "Mono 28"
+                textcolor = "#ffffff"
+                chars = "asdf"
+                offset = "-10,5"
+                margin = 0.5
+
+                [profile.swap]
+                swap = true
+                chars = "jkl"
+
+                [profile.terminals]
+                include = ["class=foot", "class=kitty"]
+            "##,
+        );
+
+        assert_eq!(
+            args_for(&path, None, &[]).unwrap(),
+            [
+                "--chars=asdf",
+                "--font=Mono 28",
+                "--margin=0.5",
+                "--offset=-10,5",
+                "--textcolor=#ffffff"
+            ]
+        );
+        assert_eq!(
+            args_for(&path, Some("swap"), &["--textcolor", "red"]).unwrap(),
+            [
+                "--chars=jkl",
+                "--font=Mono 28",
+                "--margin=0.5",
+                "--offset=-10,5",
+                "--swap"
+            ]
+        );
+        let args = args_for(&path, Some("terminals"), &[]).unwrap();
+        assert!(args.contains(&"--include=class=foot".to_string()));
+        assert!(args.contains(&"--include=class=kitty".to_string()));
+        assert!(args_for(&path, Some("missing"), &[]).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_file_args_errors() {
+        let path = write_config(
+            "errors",
+            r#"
+                [profile.broken]
+                bgcolor = "not a color"
+            "#,
+        );
+        let err = format!("{:#}", args_for(&path, Some("broken"), &[]).unwrap_err());
+        assert!(err.contains(&path.display().to_string()));
+        assert!(err.contains("'profile.broken.bgcolor'"));
+        assert!(err.contains("Invalid color format"));
+        fs::remove_file(&path).unwrap();
+
+        let path = write_config("unknown", "colour = \"red\"\n");
+        let err = format!("{:#}", args_for(&path, None, &[]).unwrap_err());
+        assert!(err.contains("unknown key 'colour'"));
+        fs::remove_file(&path).unwrap();
+
+        let path = write_config("flag", "swap = \"yes\"\n");
+        assert!(args_for(&path, None, &[]).is_err());
+        fs::remove_file(&path).unwrap();
+
+        let missing = env::temp_dir().join("wmfocus-missing.toml");
+        assert!(args_for(&missing, None, &[]).unwrap().is_empty());
+        assert!(args_for(&missing, Some("swap"), &[]).is_err());
+    }
+
+    #[test]
+    fn test_command_line_overrides_conflicting_file_args() {
+        let path = write_config(
+            "conflicts",
+            r#"
+                margin = 0.5
+                halign = "right"
+                swap = true
+                chars = "asdf"
+
+                [profile.move]
+                move = true
+            "#,
+        );
+
+        assert_eq!(
+            args_for(&path, None, &["--fill", "--move"]).unwrap(),
+            ["--chars=asdf"]
+        );
+        // The profile's `move` replaces the top level `swap` it can't be combined with.
+        let args = args_for(&path, Some("move"), &[]).unwrap();
+        assert!(args.contains(&"--move".to_string()));
+        assert!(!args.contains(&"--swap".to_string()));
+
+        let file_args = args_for(&path, None, &["--fill"]).unwrap();
+        let config = AppConfig::try_parse_from(
+            std::iter::once("wmfocus")
+                .chain(file_args.iter().map(String::as_str))
+                .chain(["--fill"]),
+        )
+        .unwrap();
+        assert!(config.fill && config.swap);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_styles() {
+        let path = write_config(
+            "styles",
+            r#"
+                [[style]]
+                match = "class=firefox"
+                bgcolor = "orange"
+
+                [[profile.swap.style]]
+                border-width = 2
+
+                [[profile.broken.style]]
+                match = "class=firefox"
+                bgcolor = "nope"
+            "#,
+        );
+        let command = AppConfig::command();
+        let matches = command.clone().get_matches_from(["wmfocus"]);
+
+        let config = load(&path, None, &command, &matches).unwrap();
+        assert!(config.args.is_empty());
+        assert_eq!(config.styles.len(), 1);
+        let config = load(&path, Some("swap"), &command, &matches).unwrap();
+        assert_eq!(config.styles.len(), 2);
+        assert_eq!(config.styles[1].border_width, Some(2.0));
+
+        let err = format!(
+            "{:#}",
+            load(&path, Some("broken"), &command, &matches).unwrap_err()
+        );
+        assert!(err.contains("'profile.broken.style[0]'"));
+        assert!(err.contains("'bgcolor'"));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,158 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Error, Result};
+use regex::Regex;
+
+use crate::DesktopWindow;
+
+/// A criterion for `--include` and `--exclude`.
+///
+/// Parsed from `class=REGEX`, `title=REGEX`, `workspace=NAME`, `output=NAME`, `floating`, `tiled`,
+/// `focused` or `urgent`.
+#[derive(Debug, Clone)]
+pub enum WindowFilter {
+    /// X11 window class or Wayland app_id matches the regex.
+    Class(Regex),
+    /// Window title matches the regex.
+    Title(Regex),
+    /// Window is on the workspace with this name.
+    Workspace(String),
+    /// Window is on the output with this name.
+    Output(String),
+    Floating,
+    Tiled,
+    Focused,
+    Urgent,
+}
+
+impl WindowFilter {
+    /// Returns true if `window` fulfills this criterion.
+    pub fn matches(&self, window: &DesktopWindow) -> bool {
+        let is_match = |regex: &Regex, value: &Option<String>| {
+            value.as_deref().is_some_and(|v| regex.is_match(v))
+        };
+        match self {
+            WindowFilter::Class(regex) => is_match(regex, &window.class),
+            WindowFilter::Title(regex) => is_match(regex, &window.title),
+            WindowFilter::Workspace(name) => window.workspace.as_ref() == Some(name),
+            WindowFilter::Output(name) => window.output.as_ref() == Some(name),
+            WindowFilter::Floating => window.is_floating,
+            WindowFilter::Tiled => !window.is_floating,
+            WindowFilter::Focused => window.is_focused,
+            WindowFilter::Urgent => window.is_urgent,
+        }
+    }
+}
+
+impl FromStr for WindowFilter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<WindowFilter> {
+        let filter = match s.split_once('=') {
+            Some(("class", regex)) => {
+                WindowFilter::Class(Regex::new(regex).context("Invalid class regex")?)
+            }
+            Some(("title", regex)) => {
+                WindowFilter::Title(Regex::new(regex).context("Invalid title regex")?)
+            }
+            Some(("workspace", name)) => WindowFilter::Workspace(name.to_string()),
+            Some(("output", name)) => WindowFilter::Output(name.to_string()),
+            None if s == "floating" => WindowFilter::Floating,
+            None if s == "tiled" => WindowFilter::Tiled,
+            None if s == "focused" => WindowFilter::Focused,
+            None if s == "urgent" => WindowFilter::Urgent,
+            _ => bail!(
+                "Unknown filter '{s}', expected class=REGEX, title=REGEX, workspace=NAME, \
+                 output=NAME, floating, tiled, focused or urgent"
+            ),
+        };
+        Ok(filter)
+    }
+}
+
+/// Keep only the windows that match any of `include` (if given) and none of `exclude`.
+pub fn filter_windows(
+    windows: Vec<DesktopWindow>,
+    include: &[WindowFilter],
+    exclude: &[WindowFilter],
+) -> Vec<DesktopWindow> {
+    windows
+        .into_iter()
+        .filter(|w| include.is_empty() || include.iter().any(|f| f.matches(w)))
+        .filter(|w| !exclude.iter().any(|f| f.matches(w)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(id: i64, class: &str, workspace: &str, is_floating: bool) -> DesktopWindow {
+        DesktopWindow {
+            id,
+            is_focused: id == 1,
+            title: Some(format!("{class} window")),
+            class: Some(class.to_string()),
+            workspace: Some(workspace.to_string()),
+            output: Some("eDP-1".to_string()),
+            is_floating,
+            ..Default::default()
+        }
+    }
+
+    fn ids(windows: &[DesktopWindow]) -> Vec<i64> {
+        windows.iter().map(|w| w.id).collect()
+    }
+
+    fn windows() -> Vec<DesktopWindow> {
+        vec![
+            window(1, "foot", "1", false),
+            window(2, "Alacritty", "1", true),
+            window(3, "firefox", "2", false),
+        ]
+    }
+
+    #[test]
+    fn test_parse_filters() {
+        assert!(matches!(
+            "class=^foot$".parse::<WindowFilter>(),
+            Ok(WindowFilter::Class(_))
+        ));
+        assert!(matches!(
+            "workspace=a=b".parse::<WindowFilter>(),
+            Ok(WindowFilter::Workspace(name)) if name == "a=b"
+        ));
+        assert!(matches!(
+            "tiled".parse::<WindowFilter>(),
+            Ok(WindowFilter::Tiled)
+        ));
+        assert!(matches!(
+            "urgent".parse::<WindowFilter>(),
+            Ok(WindowFilter::Urgent)
+        ));
+        assert!("class=(".parse::<WindowFilter>().is_err());
+        assert!("size=10".parse::<WindowFilter>().is_err());
+    }
+
+    #[test]
+    fn test_include_any_criterion() {
+        let include = vec![
+            "class=(?i)^(foot|alacritty)$".parse().unwrap(),
+            "workspace=2".parse().unwrap(),
+        ];
+        assert_eq!(ids(&filter_windows(windows(), &include, &[])), [1, 2, 3]);
+
+        let include = vec!["title=^firefox".parse().unwrap()];
+        assert_eq!(ids(&filter_windows(windows(), &include, &[])), [3]);
+    }
+
+    #[test]
+    fn test_exclude_wins_over_include() {
+        let include = vec!["workspace=1".parse().unwrap()];
+        let exclude = vec!["focused".parse().unwrap()];
+        assert_eq!(ids(&filter_windows(windows(), &include, &exclude)), [2]);
+
+        let exclude = vec!["floating".parse().unwrap(), "output=eDP-1".parse().unwrap()];
+        assert!(filter_windows(windows(), &[], &exclude).is_empty());
+    }
+}
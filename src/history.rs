@@ -0,0 +1,249 @@
+//! Focus and urgency history recorded by `wmfocus --track` for later runs.
+//!
+//! Window managers only tell which window was focused last within each container, so wmfocus
+//! can't know which window the user just left on its own. Neither do they tell when a window
+//! became urgent and Hyprland doesn't even list urgent windows. A tracking process listens to the
+//! events of the window manager instead and keeps the order in a file that every other run reads.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use anyhow::{bail, Context, Result};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+use crate::args::Backend;
+use crate::wm::{WindowEvent, WindowManager};
+use crate::DesktopWindow;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    /// Process that keeps the history up to date.
+    pid: u32,
+    /// Ids of windows, the most recently focused first.
+    focused: Vec<i64>,
+    /// Ids of urgent windows, the one that became urgent last first.
+    #[serde(default)]
+    urgent: Vec<i64>,
+}
+
+impl History {
+    /// Update the history with a change reported by the window manager.
+    pub fn record(&mut self, event: WindowEvent) {
+        match event {
+            WindowEvent::Focused(id) => {
+                self.focused.retain(|i| *i != id);
+                self.focused.insert(0, id);
+                // Focusing a window settles its urgency, Hyprland doesn't tell us otherwise.
+                self.urgent.retain(|i| *i != id);
+            }
+            WindowEvent::Urgent(id, is_urgent) => {
+                self.urgent.retain(|i| *i != id);
+                if is_urgent {
+                    self.urgent.insert(0, id);
+                }
+            }
+            WindowEvent::Closed(id) => {
+                self.focused.retain(|i| *i != id);
+                self.urgent.retain(|i| *i != id);
+            }
+        }
+    }
+
+    /// Set the `focus_history` and the urgency of `windows` from the recorded order.
+    ///
+    /// Windows that weren't focused since tracking started follow in the order the window manager
+    /// reported for them.
+    pub fn apply(&self, windows: &mut [DesktopWindow]) {
+        for window in windows.iter_mut() {
+            window.urgency_history = self.urgent.iter().position(|id| *id == window.id);
+            window.is_urgent |= window.urgency_history.is_some();
+        }
+
+        let tracked = |window: &DesktopWindow| self.focused.iter().position(|id| *id == window.id);
+        let mut order: Vec<usize> = (0..windows.len())
+            .filter(|&i| {
+                let window = &windows[i];
+                window.is_focused || window.focus_history.is_some() || tracked(window).is_some()
+            })
+            .collect();
+        order.sort_by_key(|&i| {
+            let tracked = tracked(&windows[i]);
+            (
+                !windows[i].is_focused,
+                tracked.is_none(),
+                tracked,
+                windows[i].focus_history,
+            )
+        });
+        for window in windows.iter_mut() {
+            window.focus_history = None;
+        }
+        for (rank, i) in order.into_iter().enumerate() {
+            windows[i].focus_history = Some(rank);
+        }
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        // Write to a temporary file first so that readers never see half a history.
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, serde_json::to_vec(self)?)
+            .with_context(|| format!("Couldn't write {}", temp_path.display()))?;
+        fs::rename(&temp_path, path).with_context(|| format!("Couldn't write {}", path.display()))
+    }
+}
+
+/// Whether the process `pid` is still running.
+fn is_running(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+/// Where the history for the window manager instance we're running under lives.
+fn history_path(backend: Backend) -> PathBuf {
+    let instance_var = match backend {
+        Backend::I3 => "I3SOCK",
+        Backend::Hyprland => "HYPRLAND_INSTANCE_SIGNATURE",
+        Backend::Sway => "SWAYSOCK",
+    };
+    // The socket names and the Hyprland signature differ between instances of a window manager.
+    let instance = env::var_os(instance_var)
+        .or_else(|| env::var_os("DISPLAY"))
+        .map(PathBuf::from)
+        .and_then(|p| p.file_name().map(|n| n.to_string_lossy().replace(':', "")))
+        .unwrap_or_default();
+    let dir = env::var_os("XDG_RUNTIME_DIR")
+        .filter(|d| !d.is_empty())
+        .map_or_else(env::temp_dir, PathBuf::from);
+    dir.join(format!(
+        "wmfocus-{}-{}.json",
+        backend.feature_name(),
+        instance
+    ))
+}
+
+/// Load the history that a running `--track` process keeps for `backend`.
+pub fn load(backend: Backend) -> Option<History> {
+    let path = history_path(backend);
+    let history: History = serde_json::from_slice(&fs::read(&path).ok()?)
+        .inspect_err(|e| debug!("Couldn't parse {}: {}", path.display(), e))
+        .ok()?;
+    if !is_running(history.pid) {
+        debug!("Ignoring {} since nothing tracks it", path.display());
+        return None;
+    }
+    Some(history)
+}
+
+/// Record focus and urgency changes until the window manager goes away.
+pub fn track(wm: &dyn WindowManager, backend: Backend) -> Result<()> {
+    let path = history_path(backend);
+    if let Some(history) = load(backend) {
+        bail!(
+            "wmfocus --track is already running as process {}",
+            history.pid
+        );
+    }
+
+    // Start with what the window manager knows so that --previous works right away. Windows that
+    // are already urgent are in the tree of i3 and sway but Hyprland's are lost.
+    let mut windows = wm
+        .get_windows(true)
+        .context("Couldn't get desktop windows")?;
+    windows.retain(|w| w.focus_history.is_some());
+    windows.sort_by_key(|w| w.focus_history);
+    let mut history = History {
+        pid: process::id(),
+        focused: windows.iter().map(|w| w.id).collect(),
+        urgent: vec![],
+    };
+    history.save(&path)?;
+
+    info!("Recording focus and urgency changes in {}", path.display());
+    wm.watch(&mut |event| {
+        debug!("Recording {:?}", event);
+        history.record(event);
+        history.save(&path)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracked_windows_come_first() {
+        let window = |id: i64, is_focused: bool, focus_history: Option<usize>| DesktopWindow {
+            id,
+            is_focused,
+            focus_history,
+            ..Default::default()
+        };
+        let mut windows = vec![
+            window(10, false, Some(2)),
+            window(11, true, Some(0)),
+            window(12, false, Some(1)),
+            window(13, false, None),
+            window(14, false, None),
+        ];
+
+        let mut history = History::default();
+        for event in [
+            WindowEvent::Focused(14),
+            WindowEvent::Focused(12),
+            WindowEvent::Focused(15),
+            WindowEvent::Focused(10),
+            WindowEvent::Focused(11),
+            WindowEvent::Closed(15),
+        ] {
+            history.record(event);
+        }
+        assert_eq!(history.focused, [11, 10, 12, 14]);
+
+        history.apply(&mut windows);
+        let focus_history: Vec<_> = windows.iter().map(|w| w.focus_history).collect();
+        assert_eq!(focus_history, [Some(1), Some(0), Some(2), None, Some(3)]);
+    }
+
+    #[test]
+    fn test_urgency_follows_events() {
+        let mut windows: Vec<_> = (10..14)
+            .map(|id| DesktopWindow {
+                id,
+                ..Default::default()
+            })
+            .collect();
+
+        let mut history = History::default();
+        for event in [
+            WindowEvent::Urgent(10, true),
+            WindowEvent::Urgent(11, true),
+            WindowEvent::Urgent(12, true),
+            WindowEvent::Urgent(13, true),
+            WindowEvent::Urgent(10, false),
+            WindowEvent::Focused(11),
+            WindowEvent::Closed(13),
+            WindowEvent::Urgent(12, true),
+        ] {
+            history.record(event);
+        }
+        assert_eq!(history.urgent, [12]);
+
+        history.record(WindowEvent::Urgent(10, true));
+        history.apply(&mut windows);
+        let urgency: Vec<_> = windows
+            .iter()
+            .map(|w| (w.is_urgent, w.urgency_history))
+            .collect();
+        assert_eq!(
+            urgency,
+            [
+                (true, Some(0)),
+                (false, None),
+                (true, Some(1)),
+                (false, None)
+            ]
+        );
+    }
+}
@@ -0,0 +1,289 @@
+use std::env;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use log::debug;
+
+/// Theme that every application installs its icon into and that every theme falls back to.
+const FALLBACK_THEME: &str = "hicolor";
+
+/// Icon sizes that themes commonly provide, for themes without an `index.theme`.
+const ICON_SIZES: [u32; 10] = [16, 22, 24, 32, 48, 64, 96, 128, 256, 512];
+
+/// Directories that contain icon themes, in the order of the freedesktop icon theme spec.
+fn icon_dirs() -> Vec<PathBuf> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|h| h.join(".local/share")));
+
+    let mut dirs = vec![];
+    dirs.extend(home.map(|h| h.join(".icons")));
+    dirs.extend(data_home.map(|d| d.join("icons")));
+    let data_dirs = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|d| !d.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    dirs.extend(env::split_paths(&data_dirs).map(|d| d.join("icons")));
+    dirs.push(PathBuf::from("/usr/share/pixmaps"));
+    dirs
+}
+
+/// Name of the icon theme configured for GTK, which is where most desktops put it.
+fn gtk_icon_theme() -> Option<String> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))?;
+    ["gtk-3.0", "gtk-4.0"].iter().find_map(|gtk| {
+        let settings = fs::read_to_string(config_home.join(gtk).join("settings.ini")).ok()?;
+        settings.lines().find_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            (key.trim() == "gtk-icon-theme-name")
+                .then(|| value.trim().trim_matches('"').to_string())
+                .filter(|v| !v.is_empty())
+        })
+    })
+}
+
+/// The parts of an `index.theme` that we need.
+#[derive(Debug, Default)]
+struct ThemeIndex {
+    /// Subdirectories with the nominal size of their icons.
+    directories: Vec<(String, u32)>,
+    /// Themes to fall back to.
+    inherits: Vec<String>,
+}
+
+impl ThemeIndex {
+    fn parse(contents: &str) -> ThemeIndex {
+        let mut index = ThemeIndex::default();
+        let mut directories = vec![];
+        let mut sizes = vec![];
+        let mut section = "";
+        for line in contents.lines().map(str::trim) {
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name;
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let list = || value.split(',').map(|v| v.trim().to_string());
+            match (section, key.trim()) {
+                ("Icon Theme", "Directories") => directories = list().collect(),
+                ("Icon Theme", "Inherits") => index.inherits = list().collect(),
+                (dir, "Size") => sizes.extend(value.trim().parse().map(|s| (dir.to_string(), s))),
+                _ => {}
+            }
+        }
+        index.directories = directories
+            .into_iter()
+            .filter_map(|dir| {
+                let size = sizes.iter().find(|(d, _)| *d == dir)?.1;
+                Some((dir, size))
+            })
+            .collect();
+        index
+    }
+}
+
+/// Where to look for application icons.
+///
+/// This is the selected theme followed by the themes it inherits from and finally hicolor. Icons
+/// that no theme has are looked up in the base directories themselves, which covers the legacy
+/// pixmaps directory.
+#[derive(Debug)]
+pub struct IconLookup {
+    base_dirs: Vec<PathBuf>,
+    /// Icon directories of every theme in the order they're searched, with their icon size.
+    themes: Vec<Vec<(PathBuf, u32)>>,
+}
+
+impl IconLookup {
+    pub fn new(base_dirs: Vec<PathBuf>, theme: &str) -> IconLookup {
+        let mut names = vec![theme.to_string()];
+        let mut themes = vec![];
+        while let Some(name) = names.get(themes.len()).cloned() {
+            // The first index.theme describes the theme, but its icons may be in any base dir.
+            let index = base_dirs
+                .iter()
+                .find_map(|d| fs::read_to_string(d.join(&name).join("index.theme")).ok())
+                .map(|contents| ThemeIndex::parse(&contents));
+            let directories = match &index {
+                Some(index) => index.directories.clone(),
+                None => ICON_SIZES
+                    .iter()
+                    .map(|s| (format!("{s}x{s}/apps"), *s))
+                    .collect(),
+            };
+            themes.push(
+                base_dirs
+                    .iter()
+                    .flat_map(|base| {
+                        let theme_dir = base.join(&name);
+                        directories
+                            .iter()
+                            .map(move |(dir, size)| (theme_dir.join(dir), *size))
+                    })
+                    .filter(|(dir, _)| dir.is_dir())
+                    .collect(),
+            );
+            for parent in index.into_iter().flat_map(|i| i.inherits) {
+                if !names.contains(&parent) {
+                    names.push(parent);
+                }
+            }
+            if themes.len() == names.len() && !names.iter().any(|n| n == FALLBACK_THEME) {
+                names.push(FALLBACK_THEME.to_string());
+            }
+        }
+        debug!("Searching icon themes {:?}", names);
+        IconLookup { base_dirs, themes }
+    }
+
+    /// Find the PNG icon for the application `name` that comes closest to `size` pixels.
+    ///
+    /// Names are tried as they are and in lowercase since X11 classes are usually capitalized
+    /// while icons are not. Only PNG icons are found since that's all cairo can load.
+    pub fn find_icon(&self, name: &str, size: f64) -> Option<PathBuf> {
+        if name.is_empty() || name.contains('/') {
+            return None;
+        }
+
+        let mut names = vec![name.to_string()];
+        if name.to_lowercase() != name {
+            names.push(name.to_lowercase());
+        }
+
+        // Prefer the smallest icon that doesn't need to be scaled up, then the largest one.
+        let rank = |s: u32| {
+            let s = f64::from(s);
+            (s < size, (s - size).abs().round() as i64)
+        };
+        for name in &names {
+            let file_name = format!("{name}.png");
+            for theme in &self.themes {
+                let icon = theme
+                    .iter()
+                    .map(|(dir, s)| (dir.join(&file_name), *s))
+                    .filter(|(path, _)| path.is_file())
+                    .min_by_key(|(_, s)| rank(*s));
+                if let Some((path, _)) = icon {
+                    return Some(path);
+                }
+            }
+            if let Some(path) = self
+                .base_dirs
+                .iter()
+                .map(|d| d.join(&file_name))
+                .find(|p| p.is_file())
+            {
+                return Some(path);
+            }
+        }
+        None
+    }
+}
+
+/// Load the icon for the application `name` to be drawn at `size` pixels.
+///
+/// Icons come from `theme` or, without one, from the icon theme configured for GTK.
+pub fn load_icon(name: &str, size: f64, theme: Option<&str>) -> Option<cairo::ImageSurface> {
+    static LOOKUP: OnceLock<IconLookup> = OnceLock::new();
+    let lookup = LOOKUP.get_or_init(|| {
+        let theme = theme
+            .map(str::to_string)
+            .or_else(gtk_icon_theme)
+            .unwrap_or_else(|| FALLBACK_THEME.to_string());
+        IconLookup::new(icon_dirs(), &theme)
+    });
+    let path = lookup.find_icon(name, size)?;
+    debug!("Using icon {:?} for {}", path, name);
+    let mut file = File::open(&path).ok()?;
+    cairo::ImageSurface::create_from_png(&mut file)
+        .inspect_err(|e| debug!("Couldn't load icon {:?}: {}", path, e))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch(path: PathBuf) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, b"").unwrap();
+    }
+
+    #[test]
+    fn test_find_icon_in_theme_chain() {
+        let root = env::temp_dir().join(format!("wmfocus-icons-{}", std::process::id()));
+        let (user, system, pixmaps) =
+            (root.join("user"), root.join("system"), root.join("pixmaps"));
+        fs::create_dir_all(system.join("Papirus")).unwrap();
+        fs::write(
+            system.join("Papirus/index.theme"),
+            "[Icon Theme]\nInherits=breeze\nDirectories=48x48/apps,64x64/apps\n\n\
+             [48x48/apps]\nSize=48\n\n[64x64/apps]\nSize=64\n",
+        )
+        .unwrap();
+        fs::create_dir_all(system.join("breeze")).unwrap();
+        fs::write(
+            system.join("breeze/index.theme"),
+            "[Icon Theme]\nDirectories=apps/32\n\n[apps/32]\nSize=32\nType=Fixed\n",
+        )
+        .unwrap();
+
+        // hicolor has no index.theme here, so the usual sizes are searched.
+        for s in [16, 48, 256] {
+            touch(user.join(format!("hicolor/{s}x{s}/apps/foot.png")));
+        }
+        touch(system.join("Papirus/64x64/apps/firefox.png"));
+        touch(user.join("Papirus/48x48/apps/firefox.png"));
+        touch(system.join("breeze/apps/32/kitty.png"));
+        touch(user.join("hicolor/48x48/apps/kitty.png"));
+        touch(pixmaps.join("xterm.png"));
+
+        let lookup = IconLookup::new(
+            vec![user.clone(), system.clone(), pixmaps.clone()],
+            "Papirus",
+        );
+        let icon = |name: &str, size: f64| {
+            lookup
+                .find_icon(name, size)
+                .map(|p| p.strip_prefix(&root).unwrap().to_path_buf())
+        };
+        assert_eq!(
+            icon("Foot", 32.0),
+            Some("user/hicolor/48x48/apps/foot.png".into())
+        );
+        assert_eq!(
+            icon("foot", 16.0),
+            Some("user/hicolor/16x16/apps/foot.png".into())
+        );
+        assert_eq!(
+            icon("foot", 300.0),
+            Some("user/hicolor/256x256/apps/foot.png".into())
+        );
+        // The theme's directories are merged across the base directories.
+        assert_eq!(
+            icon("firefox", 40.0),
+            Some("user/Papirus/48x48/apps/firefox.png".into())
+        );
+        assert_eq!(
+            icon("firefox", 60.0),
+            Some("system/Papirus/64x64/apps/firefox.png".into())
+        );
+        // Inherited themes come before hicolor.
+        assert_eq!(
+            icon("kitty", 48.0),
+            Some("system/breeze/apps/32/kitty.png".into())
+        );
+        assert_eq!(icon("XTerm", 48.0), Some("pixmaps/xterm.png".into()));
+        assert_eq!(icon("../foot", 32.0), None);
+        assert_eq!(icon("missing", 32.0), None);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
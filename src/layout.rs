@@ -0,0 +1,468 @@
+use crate::args::{AppConfig, HorizontalAlign, VerticalAlign};
+use crate::{DesktopWindow, HintKind};
+
+/// A rect in the format (x, y, w, h).
+pub type Rect = (i32, i32, i32, i32);
+
+/// Where the box of a hint goes and where to put its text inside of it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HintPlacement {
+    /// Box in global coordinates.
+    pub rect: Rect,
+    /// Position of the text cursor relative to the box.
+    pub draw_pos: (f64, f64),
+    /// Line from the center of the box back to where it would have been placed, if the box had to
+    /// be moved out of its window.
+    pub leader_line: Option<((i32, i32), (i32, i32))>,
+}
+
+/// Lay out the hint boxes for `hints`, each a window together with the extents of its text.
+///
+/// Boxes are placed in order and kept within the `outputs` they belong to. The placements are
+/// returned in the same order as `hints`.
+pub fn layout_hints(
+    app_config: &AppConfig,
+    hints: &[(&DesktopWindow, cairo::TextExtents)],
+    outputs: &[Rect],
+) -> Vec<HintPlacement> {
+    let mut placements: Vec<HintPlacement> = Vec::with_capacity(hints.len());
+    let mut placed = Vec::with_capacity(hints.len());
+    for (desktop_window, text_extents) in hints {
+        let placement = place_hint(app_config, desktop_window, text_extents, &placed, outputs);
+        placed.push(placement.rect);
+        placements.push(placement);
+    }
+    placements
+}
+
+/// Place the box for a hint with `text_extents` on `desktop_window` according to the alignment,
+/// offset, margin and fill settings in `app_config`.
+///
+/// If the box overlaps any of the already `placed` boxes, we try the other anchors within the
+/// window and then shift it vertically within the window. Only if all of these are taken as well,
+/// the box is nudged to the right until it's free. If that pushes it off its output, it goes to the
+/// free spot on the output that's closest to where it was supposed to be.
+fn place_hint(
+    app_config: &AppConfig,
+    desktop_window: &DesktopWindow,
+    text_extents: &cairo::TextExtents,
+    placed: &[Rect],
+    outputs: &[Rect],
+) -> HintPlacement {
+    let (width, height, margin_width, margin_height) =
+        if app_config.fill || matches!(desktop_window.kind, HintKind::Workspace(_)) {
+            (
+                desktop_window.size.0,
+                desktop_window.size.1,
+                (f64::from(desktop_window.size.0) - text_extents.width()) / 2.0,
+                (f64::from(desktop_window.size.1) - text_extents.height()) / 2.0,
+            )
+        } else {
+            let margin_factor = 1.0 + f64::from(app_config.margin);
+            (
+                (text_extents.width() * margin_factor).round() as i32,
+                (text_extents.height() * margin_factor).round() as i32,
+                ((text_extents.width() * margin_factor) - text_extents.width()) / 2.0,
+                ((text_extents.height() * margin_factor) - text_extents.height()) / 2.0,
+            )
+        };
+
+    // Due to the way cairo lays out text, we'll have to calculate the actual coordinates to
+    // put the cursor. See:
+    // https://www.cairographics.org/samples/text_align_center/
+    // https://www.cairographics.org/samples/text_extents/
+    // https://www.cairographics.org/tutorial/#L1understandingtext
+    let draw_pos = (
+        margin_width - text_extents.x_bearing(),
+        text_extents.height() + margin_height - (text_extents.height() + text_extents.y_bearing()),
+    );
+
+    // Output tiles always get their hint in the middle of the output.
+    let (horizontal_align, vertical_align) = if desktop_window.kind == HintKind::Output {
+        (HorizontalAlign::Center, VerticalAlign::Center)
+    } else {
+        (app_config.horizontal_align, app_config.vertical_align)
+    };
+
+    let window_rect = (
+        desktop_window.pos.0,
+        desktop_window.pos.1,
+        desktop_window.size.0,
+        desktop_window.size.1,
+    );
+    let anchor = |horizontal_align, vertical_align| {
+        anchor_pos(
+            app_config,
+            window_rect,
+            (width, height),
+            horizontal_align,
+            vertical_align,
+        )
+    };
+    let preferred = anchor(horizontal_align, vertical_align);
+    let output = output_for(outputs, (preferred.0, preferred.1, width, height));
+    let is_free = |rect: Rect| find_overlaps(placed, rect).is_empty();
+
+    // Try the other anchors, the ones closest to the preferred one first.
+    let mut anchors: Vec<(i32, i32)> = HORIZONTAL_ALIGNS
+        .iter()
+        .flat_map(|h| VERTICAL_ALIGNS.iter().map(|v| anchor(*h, *v)))
+        .collect();
+    anchors.sort_by_key(|a| ((a.0 - preferred.0).pow(2) + (a.1 - preferred.1).pow(2), *a));
+    anchors.dedup();
+
+    // Then shift each of them vertically within the window, which spreads out the boxes of
+    // stacked and tabbed windows that all share the same rect.
+    let shifted = anchors.clone().into_iter().flat_map(|(x, y)| {
+        let rows_above = (y - window_rect.1) / height.max(1);
+        let rows_below = (window_rect.1 + window_rect.3 - y - height) / height.max(1);
+        (1..=rows_below)
+            .map(move |row| (x, y + row * height))
+            .chain((1..=rows_above).map(move |row| (x, y - row * height)))
+    });
+
+    let candidate = anchors
+        .iter()
+        .copied()
+        .chain(shifted)
+        .map(|(x, y)| clamp_to(output, (x, y, width, height)))
+        .find(|rect| is_free(*rect));
+
+    let rect = candidate.unwrap_or_else(|| {
+        // Everything within the window is taken, so walk to the right until there's room.
+        let mut rect = clamp_to(output, (preferred.0, preferred.1, width, height));
+        let mut overlaps = find_overlaps(placed, rect);
+        while let Some(overlap) = overlaps.pop() {
+            rect.0 += overlap.2;
+            overlaps = find_overlaps(placed, rect);
+        }
+        let rect = clamp_to(output, rect);
+        match output {
+            Some(output) if !is_free(rect) => {
+                nearest_free_rect(output, placed, (preferred.0, preferred.1, width, height))
+                    .unwrap_or(rect)
+            }
+            _ => rect,
+        }
+    });
+
+    let leader_line = (!intersects(rect, window_rect)).then(|| {
+        (
+            (rect.0 + rect.2 / 2, rect.1 + rect.3 / 2),
+            (preferred.0 + width / 2, preferred.1 + height / 2),
+        )
+    });
+
+    HintPlacement {
+        rect,
+        draw_pos,
+        leader_line,
+    }
+}
+
+const HORIZONTAL_ALIGNS: [HorizontalAlign; 3] = [
+    HorizontalAlign::Left,
+    HorizontalAlign::Center,
+    HorizontalAlign::Right,
+];
+
+const VERTICAL_ALIGNS: [VerticalAlign; 3] = [
+    VerticalAlign::Top,
+    VerticalAlign::Center,
+    VerticalAlign::Bottom,
+];
+
+/// Position of a box of `size` in `window_rect` with the given alignment.
+fn anchor_pos(
+    app_config: &AppConfig,
+    window_rect: Rect,
+    size: (i32, i32),
+    horizontal_align: HorizontalAlign,
+    vertical_align: VerticalAlign,
+) -> (i32, i32) {
+    let offset = &app_config.offset;
+    let x = match horizontal_align {
+        HorizontalAlign::Left => window_rect.0 + offset.x,
+        HorizontalAlign::Center => window_rect.0 + window_rect.2 / 2 - size.0 / 2,
+        HorizontalAlign::Right => window_rect.0 + window_rect.2 - size.0 - offset.x,
+    };
+    let y = match vertical_align {
+        VerticalAlign::Top => window_rect.1 + offset.y,
+        VerticalAlign::Center => window_rect.1 + window_rect.3 / 2 - size.1 / 2,
+        VerticalAlign::Bottom => window_rect.1 + window_rect.3 - size.1 - offset.y,
+    };
+    (x, y)
+}
+
+/// The output that `rect` is on, which is the one containing its center or else the first one it
+/// overlaps.
+fn output_for(outputs: &[Rect], rect: Rect) -> Option<Rect> {
+    let center = (rect.0 + rect.2 / 2, rect.1 + rect.3 / 2, 1, 1);
+    outputs
+        .iter()
+        .find(|o| intersects(**o, center))
+        .or_else(|| outputs.iter().find(|o| intersects(**o, rect)))
+        .copied()
+}
+
+/// Move `rect` into `output` as far as it fits.
+fn clamp_to(output: Option<Rect>, rect: Rect) -> Rect {
+    let Some(output) = output else {
+        return rect;
+    };
+    let x = rect.0.min(output.0 + output.2 - rect.2).max(output.0);
+    let y = rect.1.min(output.1 + output.3 - rect.3).max(output.1);
+    (x, y, rect.2, rect.3)
+}
+
+/// The spot for `rect` on `output` that doesn't overlap any of the `placed` rects and is closest to
+/// where `rect` is, if there's any room left at all.
+///
+/// The closest spots are always right next to a placed rect or an edge of the output, so only
+/// those are tried.
+fn nearest_free_rect(output: Rect, placed: &[Rect], rect: Rect) -> Option<Rect> {
+    let xs: Vec<i32> = [rect.0, output.0, output.0 + output.2 - rect.2]
+        .into_iter()
+        .chain(placed.iter().flat_map(|p| [p.0 + p.2, p.0 - rect.2]))
+        .collect();
+    let ys: Vec<i32> = [rect.1, output.1, output.1 + output.3 - rect.3]
+        .into_iter()
+        .chain(placed.iter().flat_map(|p| [p.1 + p.3, p.1 - rect.3]))
+        .collect();
+    xs.iter()
+        .flat_map(|x| ys.iter().map(move |y| (*x, *y, rect.2, rect.3)))
+        .map(|r| clamp_to(Some(output), r))
+        .filter(|r| find_overlaps(placed, *r).is_empty())
+        .min_by_key(|r| ((r.0 - rect.0).pow(2) + (r.1 - rect.1).pow(2), r.1, r.0))
+}
+
+/// Returns true if `r1` and `r2` overlap.
+fn intersects(r1: Rect, r2: Rect) -> bool {
+    let left_corner_inside = r1.0 < r2.0 + r2.2;
+    let right_corner_inside = r1.0 + r1.2 > r2.0;
+    let top_corner_inside = r1.1 < r2.1 + r2.3;
+    let bottom_corner_inside = r1.1 + r1.3 > r2.1;
+    left_corner_inside && right_corner_inside && top_corner_inside && bottom_corner_inside
+}
+
+/// Finds the `rects` that overlap `rect`.
+fn find_overlaps(rects: &[Rect], rect: Rect) -> Vec<Rect> {
+    rects
+        .iter()
+        .copied()
+        .filter(|r| intersects(*r, rect))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn window(pos: (i32, i32), size: (i32, i32), kind: HintKind) -> DesktopWindow {
+        DesktopWindow {
+            pos,
+            size,
+            kind,
+            ..Default::default()
+        }
+    }
+
+    /// Extents of a 40x20 text, as for a two letter hint.
+    fn extents() -> cairo::TextExtents {
+        cairo::TextExtents::new(1.0, -20.0, 40.0, 20.0, 42.0, 0.0)
+    }
+
+    fn app_config(args: &[&str]) -> AppConfig {
+        AppConfig::parse_from(["wmfocus"].iter().chain(args))
+    }
+
+    #[test]
+    fn test_intersects() {
+        assert!(intersects((1905, 705, 31, 82), (1905, 723, 38, 64)));
+    }
+
+    #[test]
+    fn test_no_intersect() {
+        assert!(!intersects((1905, 705, 31, 82), (2000, 723, 38, 64)));
+    }
+
+    #[test]
+    fn test_alignments() {
+        // The window is at (100, 200) with size 400x300, the box is 60x30 with a margin of 0.5.
+        let cases = [
+            ("left", "top", (105, 210)),
+            ("left", "center", (105, 335)),
+            ("left", "bottom", (105, 460)),
+            ("center", "top", (270, 210)),
+            ("center", "center", (270, 335)),
+            ("center", "bottom", (270, 460)),
+            ("right", "top", (435, 210)),
+            ("right", "center", (435, 335)),
+            ("right", "bottom", (435, 460)),
+        ];
+        let window = window((100, 200), (400, 300), HintKind::Window);
+        for (halign, valign, pos) in cases {
+            let app_config = app_config(&[
+                "--halign", halign, "--valign", valign, "--offset", "5,10", "--margin", "0.5",
+            ]);
+            let placements = layout_hints(&app_config, &[(&window, extents())], &[]);
+            assert_eq!(
+                placements,
+                [HintPlacement {
+                    rect: (pos.0, pos.1, 60, 30),
+                    draw_pos: (9.0, 25.0),
+                    leader_line: None,
+                }],
+                "{halign} {valign}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_fill_and_tiles() {
+        let cases = [
+            // Filled windows center the text in the window.
+            (
+                vec!["--fill"],
+                HintKind::Window,
+                (100, 200, 400, 300),
+                (179.0, 160.0),
+            ),
+            // Workspace tiles are always filled.
+            (
+                vec!["--margin", "0"],
+                HintKind::Workspace(vec![]),
+                (100, 200, 400, 300),
+                (179.0, 160.0),
+            ),
+            // Output tiles are always centered.
+            (
+                vec!["--halign", "right", "--valign", "top", "--margin", "0"],
+                HintKind::Output,
+                (280, 340, 40, 20),
+                (-1.0, 20.0),
+            ),
+        ];
+        for (args, kind, rect, draw_pos) in cases {
+            let window = window((100, 200), (400, 300), kind);
+            let placements = layout_hints(&app_config(&args), &[(&window, extents())], &[]);
+            let leader_line = None;
+            assert_eq!(
+                placements,
+                [HintPlacement {
+                    rect,
+                    draw_pos,
+                    leader_line
+                }],
+                "{args:?}"
+            );
+        }
+    }
+
+    /// Lay out boxes of 40x20 for windows at `rects` and return the placements.
+    fn layout_rects(rects: &[Rect], outputs: &[Rect]) -> Vec<HintPlacement> {
+        let windows: Vec<_> = rects
+            .iter()
+            .map(|r| window((r.0, r.1), (r.2, r.3), HintKind::Window))
+            .collect();
+        let hints: Vec<_> = windows.iter().map(|w| (w, extents())).collect();
+        layout_hints(&app_config(&["--margin", "0"]), &hints, outputs)
+    }
+
+    #[test]
+    fn test_overlapping_windows() {
+        let cases = [
+            // Windows at the same position get the nearest free anchors.
+            (
+                vec![(100, 200), (100, 200), (100, 200)],
+                vec![(100, 200), (100, 340), (280, 200)],
+            ),
+            // The box only needs to avoid the ones already placed.
+            (vec![(120, 210), (100, 200)], vec![(120, 210), (100, 340)]),
+            // Boxes that only touch are left alone.
+            (
+                vec![(100, 200), (140, 200), (100, 220)],
+                vec![(100, 200), (140, 200), (100, 220)],
+            ),
+        ];
+        for (positions, expected) in cases {
+            let rects: Vec<_> = positions.iter().map(|p| (p.0, p.1, 400, 300)).collect();
+            let positions: Vec<_> = layout_rects(&rects, &[])
+                .iter()
+                .map(|p| (p.rect.0, p.rect.1))
+                .collect();
+            assert_eq!(positions, expected, "{rects:?}");
+        }
+    }
+
+    #[test]
+    fn test_stacked_windows_stay_within_window() {
+        // Stacked and tabbed windows all share the same rect.
+        let window_rect = (0, 0, 200, 100);
+        for count in 1..=12 {
+            let placements = layout_rects(&vec![window_rect; count], &[]);
+            for (i, placement) in placements.iter().enumerate() {
+                let rect = placement.rect;
+                assert!(
+                    find_overlaps(&[window_rect], rect) == [window_rect]
+                        && rect.0 >= 0
+                        && rect.1 >= 0
+                        && rect.0 + rect.2 <= 200
+                        && rect.1 + rect.3 <= 100,
+                    "{count} windows: box {i} at {rect:?} is outside of the window"
+                );
+                let others: Vec<_> = placements[..i].iter().map(|p| p.rect).collect();
+                assert!(
+                    find_overlaps(&others, rect).is_empty(),
+                    "{count} windows: box {i} at {rect:?} overlaps {others:?}"
+                );
+                assert_eq!(placement.leader_line, None);
+            }
+        }
+    }
+
+    #[test]
+    fn test_clamp_to_output() {
+        let output = (0, 0, 1000, 800);
+        let placements = layout_rects(&[(980, 790, 400, 300), (-30, -10, 400, 300)], &[output]);
+        assert_eq!(placements[0].rect, (960, 780, 40, 20));
+        assert_eq!(placements[1].rect, (0, 0, 40, 20));
+    }
+
+    #[test]
+    fn test_leader_line_for_boxes_moved_out_of_window() {
+        // The windows are too small for more than one box.
+        let output = (0, 0, 100, 100);
+        let placements = layout_rects(&[(0, 0, 30, 10); 3], &[output]);
+        let rects: Vec<_> = placements.iter().map(|p| p.rect).collect();
+        assert_eq!(rects, [(0, 0, 40, 20), (40, 0, 40, 20), (0, 20, 40, 20)]);
+        for (i, rect) in rects.iter().enumerate() {
+            assert!(find_overlaps(&rects[..i], *rect).is_empty());
+        }
+        assert_eq!(placements[0].leader_line, None);
+        assert_eq!(placements[1].leader_line, Some(((60, 10), (20, 10))));
+        assert_eq!(placements[2].leader_line, Some(((20, 30), (20, 10))));
+    }
+
+    #[test]
+    fn test_boxes_never_overlap_while_output_has_room() {
+        // Room for 5x5 boxes, the walk to the right runs out of space after the first row.
+        let output = (0, 0, 200, 100);
+        for count in 1..=25 {
+            let rects = layout_rects(&vec![(0, 0, 30, 10); count], &[output])
+                .iter()
+                .map(|p| p.rect)
+                .collect::<Vec<_>>();
+            for (i, rect) in rects.iter().enumerate() {
+                assert_eq!(clamp_to(Some(output), *rect), *rect);
+                assert!(
+                    find_overlaps(&rects[..i], *rect).is_empty(),
+                    "{count} windows: box {i} at {rect:?} overlaps {:?}",
+                    &rects[..i]
+                );
+            }
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
+use std::process;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use log::{info, warn};
 
 #[cfg(feature = "i3")]
@@ -21,7 +22,15 @@ use x11rb::{
 };
 
 mod args;
+mod config;
+mod filter;
+mod history;
+mod icons;
+mod layout;
+mod style;
+mod text;
 mod utils;
+mod wm;
 
 #[cfg(feature = "i3")]
 extern crate i3ipc;
@@ -32,136 +41,641 @@ mod wm_i3;
 #[cfg(feature = "hyprland")]
 mod wm_hyprland;
 
-#[cfg(feature = "hyprland")]
-mod wayland_render;
+#[cfg(feature = "sway")]
+mod wm_sway;
 
-#[cfg(feature = "i3")]
-use crate::wm_i3 as wm;
+#[cfg(any(feature = "i3", feature = "sway"))]
+mod wm_tree;
 
-#[cfg(feature = "hyprland")]
-use crate::wm_hyprland as wm;
+#[cfg(feature = "wayland")]
+mod wayland_render;
 
-#[derive(Debug)]
+use crate::args::{AppConfig, Backend, OutputFormat, Target};
+use crate::wm::WindowManager;
+
+#[derive(Debug, Clone)]
 pub struct DesktopWindow {
     #[allow(dead_code)]
     id: i64,
     #[allow(dead_code)]
     x_window_id: Option<i32>,
+    /// Client address for Wayland compositors that identify windows that way (Hyprland).
+    #[allow(dead_code)]
+    address: Option<String>,
+    pos: (i32, i32),
+    size: (i32, i32),
+    is_focused: bool,
+    /// Window title.
+    title: Option<String>,
+    /// X11 window class or Wayland app_id.
+    class: Option<String>,
+    /// Name of the workspace the window is on.
+    workspace: Option<String>,
+    /// Name of the output the window is on.
+    output: Option<String>,
+    is_floating: bool,
+    is_fullscreen: bool,
+    /// Whether the window demands attention.
+    is_urgent: bool,
+    /// How recently the window became urgent, 0 being the latest. Only known for urgent windows
+    /// while `wmfocus --track` runs.
+    urgency_history: Option<usize>,
+    /// Whether the window is on a visible workspace. Other windows have no on-screen geometry and
+    /// only get a position once they're arranged in the overview grid.
+    is_visible: bool,
+    /// How recently the window was focused, 0 being the focused window. Only known for windows
+    /// and only if the window manager keeps track of it.
+    focus_history: Option<usize>,
+    kind: HintKind,
+}
+
+/// Exit status when the user closed wmfocus without selecting anything.
+///
+/// Errors exit with 1 and clap uses 2 for invalid arguments.
+const EXIT_CANCELLED: i32 = 3;
+
+/// Exit status when there was nothing to select, like no windows or no previous window.
+const EXIT_NOTHING_TO_SELECT: i32 = 4;
+
+/// A visible and tiled 100x100 window at the origin for tests to adjust.
+#[cfg(test)]
+impl Default for DesktopWindow {
+    fn default() -> DesktopWindow {
+        DesktopWindow {
+            id: 1,
+            x_window_id: None,
+            address: None,
+            pos: (0, 0),
+            size: (100, 100),
+            is_focused: false,
+            title: None,
+            class: None,
+            workspace: None,
+            output: None,
+            is_floating: false,
+            is_fullscreen: false,
+            is_urgent: false,
+            urgency_history: None,
+            is_visible: true,
+            focus_history: None,
+            kind: HintKind::Window,
+        }
+    }
+}
+
+/// What a `DesktopWindow` stands for.
+///
+/// Workspaces and outputs are turned into tiles that pretend to be windows so that they can reuse
+/// the renderers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HintKind {
+    Window,
+    /// A workspace tile with a miniature of the windows on the workspace as (x, y, w, h) relative
+    /// to the workspace size.
+    Workspace(Vec<(f64, f64, f64, f64)>),
+    /// An output tile covering the whole output.
+    Output,
+}
+
+#[derive(Debug)]
+pub struct DesktopWorkspace {
+    /// Numeric id of the workspace, Hyprland switches workspaces by it.
+    id: i64,
+    name: String,
+    /// Name of the output the workspace is on.
+    output: Option<String>,
     pos: (i32, i32),
     size: (i32, i32),
     is_focused: bool,
+    is_visible: bool,
+}
+
+#[derive(Debug)]
+pub struct DesktopOutput {
+    name: String,
+    pos: (i32, i32),
+    size: (i32, i32),
 }
 
 #[cfg(feature = "i3")]
 #[derive(Debug)]
 pub struct RenderWindow<'a> {
     desktop_window: &'a DesktopWindow,
+    xcb_window_id: xproto::Window,
     cairo_context: cairo::Context,
     draw_pos: (f64, f64),
     rect: (i32, i32, i32, i32),
+    content: utils::HintContent,
 }
 
-#[cfg(feature = "i3")]
 fn main() -> Result<()> {
     pretty_env_logger::init();
-    let app_config = args::parse_args();
+    let app_config = args::parse_args()?;
+
+    let backend = match app_config.backend {
+        Some(backend) => backend,
+        None => wm::detect_backend()
+            .context("Couldn't detect the window manager, please pass --backend")?,
+    };
+    info!("Using backend {:?}", backend);
+    let wm = wm::connect(backend)?;
+
+    if app_config.track {
+        return history::track(wm.as_ref(), backend);
+    }
 
-    // Get the windows from each specific window manager implementation.
-    let desktop_windows_raw = wm::get_windows().context("Couldn't get desktop windows")?;
+    let target_workspaces = app_config.target == Target::Workspaces;
+    if app_config.target != Target::Windows && app_config.swap {
+        bail!("--swap only works with --target windows");
+    }
+    if app_config.target != Target::Outputs && app_config.move_window {
+        bail!("--move only works with --target outputs");
+    }
+    if app_config.target != Target::Windows && app_config.previous {
+        bail!("--previous only works with --target windows");
+    }
+    if app_config.target != Target::Windows && app_config.urgent {
+        bail!("--urgent only works with --target windows");
+    }
+
+    // Get the windows from each specific window manager implementation. Workspace miniatures need
+    // the windows on hidden workspaces too and so does --previous as we might have just switched
+    // workspaces. Urgent windows are usually on another workspace as well.
+    let mut desktop_windows_raw = wm
+        .get_windows(
+            app_config.all_workspaces
+                || target_workspaces
+                || app_config.previous
+                || app_config.urgent,
+        )
+        .context("Couldn't get desktop windows")?;
+    // A running `wmfocus --track` knows the focus order across containers and workspaces.
+    if let Some(history) = history::load(backend) {
+        history.apply(&mut desktop_windows_raw);
+    }
+
+    // Remember the active window for --swap before it might get filtered out.
+    let active_window = desktop_windows_raw.iter().find(|w| w.is_focused).cloned();
+    let desktop_windows_raw = filter::filter_windows(
+        desktop_windows_raw,
+        &app_config.include,
+        &app_config.exclude,
+    );
 
     // Sort by position to make hint position more deterministic.
-    let desktop_windows = utils::sort_by_pos(desktop_windows_raw);
+    let mut desktop_windows = utils::sort_by_pos(desktop_windows_raw);
 
-    let (conn, screen_num) = XCBConnection::connect(None).context("No Xorg connection")?;
-    let screen = &conn.setup().roots[screen_num];
+    // Without an urgent window, --urgent shows the usual hints.
+    let jump_to_urgent = app_config.urgent && desktop_windows.iter().any(|w| w.is_urgent);
+    if app_config.urgent && !jump_to_urgent && !app_config.all_workspaces {
+        desktop_windows.retain(|w| w.is_visible);
+    }
 
-    // Assemble RenderWindows from DesktopWindows.
-    let mut render_windows = HashMap::new();
-    for desktop_window in &desktop_windows {
-        // We need to estimate the font size before rendering because we want the window to only be
-        // the size of the font.
-        let hint = utils::get_next_hint(
-            render_windows.keys().collect(),
-            &app_config.hint_chars,
-            desktop_windows.len(),
-        )
-        .context("Couldn't get next hint")?;
+    let desktop_workspaces = if target_workspaces {
+        wm.get_workspaces().context("Couldn't get workspaces")?
+    } else {
+        vec![]
+    };
+    let desktop_outputs = if app_config.target == Target::Outputs {
+        wm.get_outputs().context("Couldn't get outputs")?
+    } else {
+        vec![]
+    };
+
+    // Output tiles cover their outputs. Workspace tiles and windows on hidden workspaces are shown
+    // on the output that is currently in use.
+    let mut unarranged_windows = None;
+    if app_config.target == Target::Outputs {
+        let active_output = active_window.as_ref().and_then(|w| w.output.as_deref());
+        desktop_windows = utils::output_tiles(&desktop_outputs, active_output);
+    } else if target_workspaces {
+        let focused_output = desktop_workspaces
+            .iter()
+            .find(|w| w.is_focused)
+            .and_then(|w| w.output.as_deref());
+        let area = overview_area(wm.as_ref(), focused_output)?;
+        desktop_windows = utils::workspace_tiles(&desktop_workspaces, &desktop_windows, area);
+    } else if app_config.all_workspaces {
+        let active_output = active_window.as_ref().and_then(|w| w.output.as_deref());
+        let area = overview_area(wm.as_ref(), active_output)?;
+        unarranged_windows = Some(desktop_windows.clone());
+        utils::arrange_hidden_windows(&mut desktop_windows, area, app_config.font.font_size);
+    }
 
-        // Figure out how large the window actually needs to be.
-        let text_extents = utils::extents_for_text(
-            &hint,
-            &app_config.font.font_family,
-            app_config.font.font_size,
-        )
-        .context("Couldn't create extents for text")?;
-        let (width, height, margin_width, margin_height) = if app_config.fill {
+    if desktop_windows.is_empty() {
+        warn!("No windows found");
+        process::exit(EXIT_NOTHING_TO_SELECT);
+    }
+
+    let hints = utils::assign_hints(
+        &desktop_windows,
+        &app_config.hint_chars,
+        app_config.hint_priority,
+        active_window.as_ref(),
+    )
+    .context("Couldn't assign hints")?;
+
+    let selection = if app_config.previous {
+        let Some(previous) = previous_window(&desktop_windows, &hints) else {
+            info!("There's no previous window");
+            process::exit(EXIT_NOTHING_TO_SELECT);
+        };
+        Some(previous)
+    } else if jump_to_urgent {
+        urgent_window(&desktop_windows, &hints)
+    } else {
+        match backend {
+            #[cfg(feature = "i3")]
+            Backend::I3 => run_x11(&app_config, wm.as_ref(), &desktop_windows, &hints)?,
+            #[cfg(feature = "hyprland")]
+            Backend::Hyprland => run_wayland(&app_config, wm.as_ref(), &desktop_windows, &hints)?,
+            #[cfg(feature = "sway")]
+            Backend::Sway => run_wayland(&app_config, wm.as_ref(), &desktop_windows, &hints)?,
+            #[allow(unreachable_patterns)]
+            _ => bail!("No renderer available for {:?}", backend),
+        }
+    };
+
+    let Some((hint, selected_window)) = selection else {
+        info!("No window was selected");
+        process::exit(EXIT_CANCELLED);
+    };
+    // The overview grid only decides where the hints go, the window keeps its real geometry.
+    let selected_window = match (&unarranged_windows, hints.iter().position(|h| *h == hint)) {
+        (Some(windows), Some(index)) => &windows[index],
+        _ => selected_window,
+    };
+
+    if target_workspaces {
+        // Workspace tiles are identified by their index.
+        let selected_workspace = &desktop_workspaces[selected_window.id as usize];
+        if app_config.print_only {
+            let output = match app_config.output.unwrap_or(OutputFormat::Id) {
+                OutputFormat::Id => selected_workspace.name.clone(),
+                OutputFormat::Json => serde_json::to_string(&utils::SelectedWorkspace::new(
+                    backend,
+                    &hint,
+                    selected_workspace,
+                ))
+                .context("Couldn't serialize workspace")?,
+            };
+            println!("{output}");
+        } else {
+            info!("Switching to workspace {}", selected_workspace.name);
+            wm.focus_workspace(selected_workspace)
+                .context("Couldn't switch workspace")?;
+        }
+        return Ok(());
+    }
+
+    if app_config.target == Target::Outputs {
+        // Output tiles are identified by their index.
+        let selected_output = &desktop_outputs[selected_window.id as usize];
+        if app_config.print_only {
+            let output = match app_config.output.unwrap_or(OutputFormat::Id) {
+                OutputFormat::Id => selected_output.name.clone(),
+                OutputFormat::Json => serde_json::to_string(&utils::SelectedOutput::new(
+                    backend,
+                    &hint,
+                    selected_output,
+                ))
+                .context("Couldn't serialize output")?,
+            };
+            println!("{output}");
+        } else if app_config.move_window {
+            let Some(active_window) = &active_window else {
+                warn!("There's no active window.");
+                return Ok(());
+            };
+            info!("Moving window to output {}", selected_output.name);
+            wm.move_window_to_output(active_window, selected_output)
+                .context("Couldn't move window")?;
+        } else {
+            info!("Focusing output {}", selected_output.name);
+            wm.focus_output(selected_output)
+                .context("Couldn't focus output")?;
+        }
+        return Ok(());
+    }
+
+    if app_config.print_only {
+        let output = match app_config.output.unwrap_or(OutputFormat::Id) {
+            OutputFormat::Id => wm
+                .describe_window(selected_window, app_config.print_details)
+                .context("Couldn't describe window")?,
+            OutputFormat::Json => {
+                serde_json::to_string(&utils::SelectedWindow::new(backend, &hint, selected_window))
+                    .context("Couldn't serialize window")?
+            }
+        };
+        println!("{output}");
+    } else if app_config.swap {
+        let Some(active_window) = &active_window else {
+            warn!("There's no active window.");
+            return Ok(());
+        };
+        wm.swap_windows(active_window, selected_window)
+            .context("Couldn't swap windows")?;
+    } else {
+        info!(
+            "Focusing window at ({}, {})",
+            selected_window.pos.0, selected_window.pos.1
+        );
+        wm.focus_window(selected_window)
+            .context("Couldn't focus window")?;
+    }
+
+    Ok(())
+}
+
+/// The most recently focused of `desktop_windows` apart from the focused one, together with its
+/// hint.
+fn previous_window<'a>(
+    desktop_windows: &'a [DesktopWindow],
+    hints: &[String],
+) -> Option<(String, &'a DesktopWindow)> {
+    hints
+        .iter()
+        .zip(desktop_windows)
+        .filter(|(_, w)| w.focus_history.is_some_and(|n| n > 0))
+        .min_by_key(|(_, w)| w.focus_history)
+        .map(|(hint, w)| (hint.clone(), w))
+}
+
+/// The urgent window of `desktop_windows` that became urgent last, together with its hint.
+///
+/// Window managers don't tell when a window became urgent, only `wmfocus --track` knows. Without
+/// it, the urgent window that was focused most recently wins.
+fn urgent_window<'a>(
+    desktop_windows: &'a [DesktopWindow],
+    hints: &[String],
+) -> Option<(String, &'a DesktopWindow)> {
+    hints
+        .iter()
+        .zip(desktop_windows)
+        .filter(|(_, w)| w.is_urgent)
+        .min_by_key(|(_, w)| {
             (
-                desktop_window.size.0 as u16,
-                desktop_window.size.1 as u16,
-                (f64::from(desktop_window.size.0) - text_extents.width()) / 2.0,
-                (f64::from(desktop_window.size.1) - text_extents.height()) / 2.0,
+                w.urgency_history.is_none(),
+                w.urgency_history,
+                w.focus_history.is_none(),
+                w.focus_history,
             )
-        } else {
-            let margin_factor = 1.0 + 0.2;
+        })
+        .map(|(hint, w)| (hint.clone(), w))
+}
+
+/// Return the area (x, y, w, h) of the output called `output_name` or of the first output.
+fn overview_area(
+    wm: &dyn WindowManager,
+    output_name: Option<&str>,
+) -> Result<(i32, i32, i32, i32)> {
+    let desktop_outputs = wm.get_outputs().context("Couldn't get outputs")?;
+    let output = desktop_outputs
+        .iter()
+        .find(|o| Some(o.name.as_str()) == output_name)
+        .or(desktop_outputs.first())
+        .context("Couldn't find an output for the overview")?;
+    Ok((output.pos.0, output.pos.1, output.size.0, output.size.1))
+}
+
+/// Create a window with an alpha channel that covers the whole screen to darken the desktop with
+/// `--dim`.
+///
+/// Returns the window together with a context for drawing onto it, or `None` if the X server has no
+/// 32 bit visual.
+#[cfg(feature = "i3")]
+fn create_dim_window(
+    conn: &XCBConnection,
+    screen: &xproto::Screen,
+) -> Result<Option<(xproto::Window, cairo::Context)>> {
+    let Some(visual_id) = screen
+        .allowed_depths
+        .iter()
+        .filter(|d| d.depth == 32)
+        .flat_map(|d| &d.visuals)
+        .find(|v| v.class == xproto::VisualClass::TRUE_COLOR)
+        .map(|v| v.visual_id)
+    else {
+        warn!("No visual with an alpha channel, can't dim the desktop");
+        return Ok(None);
+    };
+
+    let colormap = conn.generate_id()?;
+    conn.create_colormap(
+        xproto::ColormapAlloc::NONE,
+        colormap,
+        screen.root,
+        visual_id,
+    )?;
+
+    let xcb_window_id = conn.generate_id()?;
+    let win_aux = xproto::CreateWindowAux::new()
+        .event_mask(xproto::EventMask::EXPOSURE)
+        .background_pixel(0)
+        .border_pixel(0)
+        .colormap(colormap)
+        .override_redirect(1);
+    xproto::create_window(
+        conn,
+        32,
+        xcb_window_id,
+        screen.root,
+        0,
+        0,
+        screen.width_in_pixels,
+        screen.height_in_pixels,
+        0,
+        xproto::WindowClass::INPUT_OUTPUT,
+        visual_id,
+        &win_aux,
+    )?;
+    conn.map_window(xcb_window_id)?;
+    conn.flush()?;
+
+    let mut visual = utils::find_xcb_visualtype(conn, visual_id).context("Couldn't find visual")?;
+    let cairo_conn =
+        unsafe { cairo::XCBConnection::from_raw_none(conn.get_raw_xcb_connection() as _) };
+    let cairo_visual = unsafe { cairo::XCBVisualType::from_raw_none(&mut visual as *mut _ as _) };
+    let surface = cairo::XCBSurface::create(
+        &cairo_conn,
+        &cairo::XCBDrawable(xcb_window_id),
+        &cairo_visual,
+        i32::from(screen.width_in_pixels),
+        i32::from(screen.height_in_pixels),
+    )
+    .context("Couldn't create Cairo Surface")?;
+    Ok(Some((
+        xcb_window_id,
+        cairo::Context::new(&surface).context("Couldn't create Cairo Context")?,
+    )))
+}
+
+/// Darken the desktop underneath the hints, the windows that still match `pressed_keys` stay lit.
+#[cfg(feature = "i3")]
+fn draw_dim_window(
+    cairo_context: &cairo::Context,
+    dim_color: (f64, f64, f64, f64),
+    render_windows: &HashMap<String, RenderWindow>,
+    pressed_keys: &str,
+) -> Result<()> {
+    let windows: Vec<_> = render_windows
+        .iter()
+        .map(|(hint, rw)| {
             (
-                (text_extents.width() * margin_factor).round() as u16,
-                (text_extents.height() * margin_factor).round() as u16,
-                ((text_extents.width() * margin_factor) - text_extents.width()) / 2.0,
-                ((text_extents.height() * margin_factor) - text_extents.height()) / 2.0,
+                rw.desktop_window,
+                &rw.content.style,
+                hint.starts_with(pressed_keys),
             )
-        };
+        })
+        .collect();
+    utils::draw_dim_overlay(cairo_context, dim_color, &windows)?;
+    cairo_context.target().flush();
+    Ok(())
+}
 
-        // Due to the way cairo lays out text, we'll have to calculate the actual coordinates to
-        // put the cursor. See:
-        // https://www.cairographics.org/samples/text_align_center/
-        // https://www.cairographics.org/samples/text_extents/
-        // https://www.cairographics.org/tutorial/#L1understandingtext
-        let draw_pos = (
-            margin_width - text_extents.x_bearing(),
-            text_extents.height() + margin_height
-                - (text_extents.height() + text_extents.y_bearing()),
-        );
+/// Width of the lines pointing from moved hint boxes back to their windows.
+#[cfg(feature = "i3")]
+const LEADER_LINE_WIDTH: i32 = 2;
 
+/// Show the leader line from `from` to `to` in `color`.
+///
+/// Without a compositor, X11 can't draw anything outside of windows, so instead of the straight
+/// line on Wayland this is an elbow of a horizontal and a vertical window that only the X server
+/// has to paint.
+#[cfg(feature = "i3")]
+fn create_leader_line(
+    conn: &XCBConnection,
+    screen: &xproto::Screen,
+    (from, to): ((i32, i32), (i32, i32)),
+    color: (f64, f64, f64, f64),
+) -> Result<()> {
+    let visual =
+        utils::find_xcb_visualtype(conn, screen.root_visual).context("Couldn't find visual")?;
+    let channel = |c: f64, mask: u32| {
+        let shift = mask.trailing_zeros();
+        ((c.clamp(0.0, 1.0) * f64::from(mask >> shift)).round() as u32) << shift
+    };
+    let pixel = channel(color.0, visual.red_mask)
+        | channel(color.1, visual.green_mask)
+        | channel(color.2, visual.blue_mask);
+    let opacity_atom = conn
+        .intern_atom(false, b"_NET_WM_WINDOW_OPACITY")?
+        .reply()
+        .context("Couldn't create atom _NET_WM_WINDOW_OPACITY")?
+        .atom;
+
+    let segments = [
+        (
+            from.0.min(to.0),
+            from.1,
+            (from.0 - to.0).abs(),
+            LEADER_LINE_WIDTH,
+        ),
+        (
+            to.0,
+            from.1.min(to.1),
+            LEADER_LINE_WIDTH,
+            (from.1 - to.1).abs(),
+        ),
+    ];
+    for (x, y, width, height) in segments {
+        if width == 0 || height == 0 {
+            continue;
+        }
+        let xcb_window_id = conn.generate_id()?;
+        let win_aux = xproto::CreateWindowAux::new()
+            .background_pixel(pixel)
+            .override_redirect(1);
+        xproto::create_window(
+            conn,
+            x11rb::COPY_FROM_PARENT as u8,
+            xcb_window_id,
+            screen.root,
+            (x - LEADER_LINE_WIDTH / 2) as i16,
+            (y - LEADER_LINE_WIDTH / 2) as i16,
+            width as u16,
+            height as u16,
+            0,
+            xproto::WindowClass::INPUT_OUTPUT,
+            screen.root_visual,
+            &win_aux,
+        )?;
+        conn.change_property32(
+            xproto::PropMode::REPLACE,
+            xcb_window_id,
+            opacity_atom,
+            xproto::AtomEnum::CARDINAL,
+            &[(f64::from(u32::MAX) * color.3) as u32],
+        )?;
+        conn.map_window(xcb_window_id)?;
+    }
+    Ok(())
+}
+
+/// Draw hints as X11 override-redirect windows and return the selected hint and window.
+#[cfg(feature = "i3")]
+fn run_x11<'a>(
+    app_config: &AppConfig,
+    wm: &dyn WindowManager,
+    desktop_windows: &'a [DesktopWindow],
+    hints: &[String],
+) -> Result<Option<(String, &'a DesktopWindow)>> {
+    let (conn, screen_num) = XCBConnection::connect(None).context("No Xorg connection")?;
+    let screen = &conn.setup().roots[screen_num];
+
+    // We need to estimate the font size before rendering because we want the window to only be
+    // the size of the font.
+    let mut contents = vec![];
+    let mut text_extents = vec![];
+    for (hint, desktop_window) in hints.iter().zip(desktop_windows) {
+        let content = utils::hint_content(app_config, hint, desktop_window)
+            .context("Couldn't create extents for text")?;
+        text_extents.push((desktop_window, content.extents));
+        contents.push(content);
+    }
+    let output_rects: Vec<_> = wm
+        .get_outputs()
+        .context("Couldn't get outputs")?
+        .iter()
+        .map(|o| (o.pos.0, o.pos.1, o.size.0, o.size.1))
+        .collect();
+    let placements = layout::layout_hints(app_config, &text_extents, &output_rects);
+
+    // The dim window has to exist before the hints so that it ends up below them.
+    let dim_window = match app_config.dim {
+        Some(dim_color) => {
+            create_dim_window(&conn, screen)?.map(|(window, cr)| (window, cr, dim_color))
+        }
+        None => None,
+    };
+
+    // Leader lines come before the hints as well so that they don't cover any of them.
+    for (placement, content) in placements.iter().zip(&contents) {
+        if let Some(leader_line) = placement.leader_line {
+            create_leader_line(&conn, screen, leader_line, content.style.bg_color)?;
+        }
+    }
+
+    // Assemble RenderWindows from DesktopWindows.
+    let mut render_windows = HashMap::new();
+    for (((hint, desktop_window), placement), content) in hints
+        .iter()
+        .cloned()
+        .zip(desktop_windows)
+        .zip(placements)
+        .zip(contents)
+    {
         debug!(
             "Spawning RenderWindow for this DesktopWindow: {:?}",
             desktop_window
         );
 
-        let x_offset = app_config.offset.x;
-        let mut x = match app_config.horizontal_align {
-            args::HorizontalAlign::Left => (desktop_window.pos.0 + x_offset) as i16,
-            args::HorizontalAlign::Center => {
-                (desktop_window.pos.0 + desktop_window.size.0 / 2 - i32::from(width) / 2) as i16
-            }
-            args::HorizontalAlign::Right => {
-                (desktop_window.pos.0 + desktop_window.size.0 - i32::from(width) - x_offset) as i16
-            }
-        };
-
-        let y_offset = app_config.offset.y;
-        let y = match app_config.vertical_align {
-            args::VerticalAlign::Top => (desktop_window.pos.1 + y_offset) as i16,
-            args::VerticalAlign::Center => {
-                (desktop_window.pos.1 + desktop_window.size.1 / 2 - i32::from(height) / 2) as i16
-            }
-            args::VerticalAlign::Bottom => {
-                (desktop_window.pos.1 + desktop_window.size.1 - i32::from(height) - y_offset) as i16
-            }
-        };
-
-        // If this is overlapping then we'll nudge the new RenderWindow a little bit out of the
-        // way.
-        let mut overlaps = utils::find_overlaps(
-            render_windows.values().collect(),
-            (x.into(), y.into(), width.into(), height.into()),
-        );
-        while !overlaps.is_empty() {
-            x += overlaps.pop().unwrap().2 as i16;
-            overlaps = utils::find_overlaps(
-                render_windows.values().collect(),
-                (x.into(), y.into(), width.into(), height.into()),
-            );
-        }
+        let layout::HintPlacement {
+            rect: (x, y, width, height),
+            draw_pos,
+            ..
+        } = placement;
 
         let xcb_window_id = conn.generate_id()?;
 
@@ -181,10 +695,10 @@ fn main() -> Result<()> {
             x11rb::COPY_FROM_PARENT as u8,
             xcb_window_id,
             screen.root,
-            x,
-            y,
-            width,
-            height,
+            x as i16,
+            y as i16,
+            width as u16,
+            height as u16,
             0,
             xproto::WindowClass::INPUT_OUTPUT,
             screen.root_visual,
@@ -199,7 +713,7 @@ fn main() -> Result<()> {
             .reply()
             .context("Couldn't create atom _NET_WM_WINDOW_OPACITY")?
             .atom;
-        let opacity = (0xFFFFFFFFu64 as f64 * app_config.bg_color.3) as u64;
+        let opacity = (0xFFFFFFFFu64 as f64 * content.style.bg_color.3) as u64;
         conn.change_property32(
             xproto::PropMode::REPLACE,
             xcb_window_id,
@@ -221,8 +735,8 @@ fn main() -> Result<()> {
             &cairo_conn,
             &cairo::XCBDrawable(xcb_window_id),
             &cairo_visual,
-            width.into(),
-            height.into(),
+            width,
+            height,
         )
         .context("Couldn't create Cairo Surface")?;
         let cairo_context =
@@ -230,9 +744,11 @@ fn main() -> Result<()> {
 
         let render_window = RenderWindow {
             desktop_window,
+            xcb_window_id,
             cairo_context,
             draw_pos,
-            rect: (x.into(), y.into(), width.into(), height.into()),
+            rect: (x, y, width, height),
+            content,
         };
 
         render_windows.insert(hint, render_window);
@@ -249,6 +765,7 @@ fn main() -> Result<()> {
     // We'll have to track the keys pressed so far.
     let mut pressed_keys = String::default();
     let mut sequence = utils::Sequence::new(None);
+    let mut selection = None;
 
     let mut closed = false;
     while !closed {
@@ -256,12 +773,28 @@ fn main() -> Result<()> {
         let event_option = Some(event);
         if let Some(e) = event_option {
             match e {
-                Event::Expose(_) => {
-                    for (hint, rw) in &render_windows {
-                        utils::draw_hint_text(rw, &app_config, hint, &pressed_keys)
+                // Only repaint the window that was exposed, and only once for a series of
+                // exposures.
+                Event::Expose(e) if e.count == 0 => {
+                    if let Some((window, cairo_context, dim_color)) = &dim_window {
+                        if e.window == *window {
+                            draw_dim_window(
+                                cairo_context,
+                                *dim_color,
+                                &render_windows,
+                                &pressed_keys,
+                            )
+                            .context("Couldn't dim the desktop")?;
+                        }
+                    }
+                    for rw in render_windows
+                        .values()
+                        .filter(|rw| rw.xcb_window_id == e.window)
+                    {
+                        utils::draw_hint_text(rw, app_config, &pressed_keys)
                             .context("Couldn't draw hint text")?;
-                        conn.flush()?;
                     }
+                    conn.flush()?;
                 }
                 Event::ButtonPress(_) => {
                     closed = true;
@@ -311,28 +844,23 @@ fn main() -> Result<()> {
                     if sequence.is_started() {
                         utils::remove_last_key(&mut pressed_keys, &kstr);
                     } else if let Some(rw) = &render_windows.get(&pressed_keys) {
-                        info!("Found matching window, focusing");
-                        if app_config.print_only {
-                            println!("0x{:x}", rw.desktop_window.x_window_id.unwrap_or(0));
-                        } else if app_config.swap {
-                            let Some(active_window) =
-                                desktop_windows.iter().find(|window| window.is_focused)
-                            else {
-                                warn!("There's no active window.");
-                                closed = true;
-                                continue;
-                            };
-                            wm::swap_windows(active_window, rw.desktop_window)
-                                .context("Couldn't swap windows")?;
-                        } else {
-                            wm::focus_window(rw.desktop_window).context("Couldn't focus window")?;
-                        }
+                        info!("Found matching window");
+                        selection = Some((pressed_keys.clone(), rw.desktop_window));
                         closed = true;
                     } else if !pressed_keys.is_empty()
                         && render_windows.keys().any(|k| k.starts_with(&pressed_keys))
                     {
-                        for (hint, rw) in &render_windows {
-                            utils::draw_hint_text(rw, &app_config, hint, &pressed_keys)
+                        if let Some((_, cairo_context, dim_color)) = &dim_window {
+                            draw_dim_window(
+                                cairo_context,
+                                *dim_color,
+                                &render_windows,
+                                &pressed_keys,
+                            )
+                            .context("Couldn't dim the desktop")?;
+                        }
+                        for rw in render_windows.values() {
+                            utils::draw_hint_text(rw, app_config, &pressed_keys)
                                 .context("Couldn't draw hint text")?;
                             conn.flush()?;
                         }
@@ -350,60 +878,31 @@ fn main() -> Result<()> {
         }
     }
 
-    Ok(())
+    Ok(selection)
 }
 
-#[cfg(feature = "hyprland")]
-fn main() -> Result<()> {
+/// Draw hints on a Wayland layer surface and return the selected hint and window.
+#[cfg(feature = "wayland")]
+fn run_wayland<'a>(
+    app_config: &AppConfig,
+    wm: &dyn WindowManager,
+    desktop_windows: &'a [DesktopWindow],
+    hints: &[String],
+) -> Result<Option<(String, &'a DesktopWindow)>> {
     use crate::wayland_render::WaylandRenderer;
 
-    pretty_env_logger::init();
-    let app_config = args::parse_args();
-
-    // Get visible windows from Hyprland
-    let desktop_windows_raw = wm::get_windows().context("Couldn't get desktop windows")?;
-
-    if desktop_windows_raw.is_empty() {
-        warn!("No windows found");
-        return Ok(());
-    }
-
-    // Sort by position to make hint position more deterministic
-    let desktop_windows = utils::sort_by_pos(desktop_windows_raw);
-
     info!("Found {} windows", desktop_windows.len());
 
-    // Generate hints for each window
-    let mut hints = HashMap::new();
-    let hint_chars = app_config.hint_chars.clone();
-    for window in &desktop_windows {
-        let hint = utils::get_next_hint(hints.keys().collect(), &hint_chars, desktop_windows.len())?;
-        hints.insert(hint, window);
-    }
+    let hints: HashMap<_, _> = hints.iter().cloned().zip(desktop_windows).collect();
+
+    let desktop_outputs = wm.get_outputs().context("Couldn't get outputs")?;
 
     // Create Wayland renderer and display hints
-    let mut renderer = WaylandRenderer::new(app_config)?;
+    let mut renderer = WaylandRenderer::new(app_config, desktop_outputs)?;
 
     // Render hints on windows
-    renderer.render_hints(&desktop_windows, &hints)?;
-
-    // Wait for user input and focus selected window
-    if let Some(selected_window) = renderer.wait_for_hint_selection(&hints)? {
-        info!("Focusing window at ({}, {})", selected_window.pos.0, selected_window.pos.1);
-        wm::focus_window(selected_window)?;
-    }
-
-    Ok(())
-}
+    renderer.render_hints(desktop_windows, &hints)?;
 
-#[cfg(not(any(feature = "i3", feature = "hyprland")))]
-fn main() -> Result<()> {
-    eprintln!(
-        "You need to enable support for at least one window manager.\n
-Currently supported:
-    --features i3
-    --features hyprland"
-    );
-
-    Ok(())
+    // Wait for user input
+    renderer.wait_for_hint_selection(&hints)
 }
@@ -0,0 +1,216 @@
+use anyhow::{bail, Context, Result};
+
+use crate::args::{parse_color, AppConfig};
+use crate::filter::WindowFilter;
+use crate::text::Color;
+use crate::{utils, DesktopWindow};
+
+/// How a single hint is drawn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HintStyle {
+    pub bg_color: Color,
+    pub text_color: Color,
+    /// Color of the already typed chars.
+    pub text_color_alt: Color,
+    /// Final font size, already scaled for overview entries and outputs.
+    pub font_size: f64,
+    /// Width and color of the outline around the hint box.
+    pub border: Option<(f64, Color)>,
+}
+
+/// A `[[style]]` table from the config file.
+///
+/// Every field that is set overrides the style of the windows matching any of `criteria`. Rules
+/// without criteria apply to all windows.
+#[derive(Debug, Clone, Default)]
+pub struct StyleRule {
+    pub criteria: Vec<WindowFilter>,
+    pub bg_color: Option<Color>,
+    pub text_color: Option<Color>,
+    pub text_color_alt: Option<Color>,
+    pub font_size: Option<f64>,
+    pub border_width: Option<f64>,
+    pub border_color: Option<Color>,
+}
+
+impl StyleRule {
+    /// Parse a rule from its table in the config file.
+    ///
+    /// `match` takes the same criteria as `--include`, colors are in CSS notation like on the
+    /// command line.
+    pub fn from_table(table: &toml::Table) -> Result<StyleRule> {
+        let mut rule = StyleRule::default();
+        for (key, value) in table {
+            let context = || format!("invalid value for '{key}'");
+            match key.as_str() {
+                "match" => {
+                    let criteria = match value {
+                        toml::Value::String(s) => vec![s.as_str()],
+                        toml::Value::Array(values) => values
+                            .iter()
+                            .map(|v| v.as_str().context("expected a list of strings"))
+                            .collect::<Result<_>>()
+                            .with_context(context)?,
+                        _ => bail!("{}: expected a string or a list of strings", context()),
+                    };
+                    rule.criteria = criteria
+                        .into_iter()
+                        .map(|c| c.parse())
+                        .collect::<Result<_>>()
+                        .with_context(context)?;
+                }
+                "bgcolor" => rule.bg_color = Some(color(value).with_context(context)?),
+                "textcolor" => rule.text_color = Some(color(value).with_context(context)?),
+                "textcoloralt" => rule.text_color_alt = Some(color(value).with_context(context)?),
+                "font-size" => rule.font_size = Some(size(value).with_context(context)?),
+                "border-width" => rule.border_width = Some(size(value).with_context(context)?),
+                "border-color" => rule.border_color = Some(color(value).with_context(context)?),
+                _ => bail!(
+                    "unknown key '{key}', expected match, bgcolor, textcolor, textcoloralt, \
+                     font-size, border-width or border-color"
+                ),
+            }
+        }
+        Ok(rule)
+    }
+
+    fn matches(&self, window: &DesktopWindow) -> bool {
+        self.criteria.is_empty() || self.criteria.iter().any(|c| c.matches(window))
+    }
+}
+
+fn color(value: &toml::Value) -> Result<Color> {
+    let color = value.as_str().context("expected a color")?;
+    parse_color(color).map_err(anyhow::Error::msg)
+}
+
+fn size(value: &toml::Value) -> Result<f64> {
+    let size = match value {
+        toml::Value::Integer(i) => *i as f64,
+        toml::Value::Float(f) => *f,
+        _ => bail!("expected a number"),
+    };
+    if !size.is_finite() || size < 0.0 {
+        bail!("expected a positive number");
+    }
+    Ok(size)
+}
+
+/// Resolve the style of the hint for `window`.
+///
+/// Focused windows start out with the `*_current` colors and urgent windows with the urgent
+/// background. All matching rules are applied on top in the order they're configured, so later
+/// rules win.
+pub fn hint_style(app_config: &AppConfig, window: &DesktopWindow) -> HintStyle {
+    let (mut bg_color, mut text_color, mut text_color_alt) = if window.is_focused {
+        (
+            app_config.bg_color_current,
+            app_config.text_color_current,
+            app_config.text_color_current_alt,
+        )
+    } else {
+        (
+            app_config.bg_color,
+            app_config.text_color,
+            app_config.text_color_alt,
+        )
+    };
+    if window.is_urgent {
+        bg_color = app_config.bg_color_urgent;
+    }
+    let mut font_size = app_config.font.font_size;
+    let mut border_width = None;
+    let mut border_color = None;
+
+    for rule in app_config.styles.iter().filter(|r| r.matches(window)) {
+        bg_color = rule.bg_color.unwrap_or(bg_color);
+        text_color = rule.text_color.unwrap_or(text_color);
+        text_color_alt = rule.text_color_alt.unwrap_or(text_color_alt);
+        font_size = rule.font_size.unwrap_or(font_size);
+        border_width = rule.border_width.or(border_width);
+        border_color = rule.border_color.or(border_color);
+    }
+
+    HintStyle {
+        bg_color,
+        text_color,
+        text_color_alt,
+        font_size: utils::hint_font_size(font_size, window),
+        border: border_width
+            .filter(|w| *w > 0.0)
+            .map(|w| (w, border_color.unwrap_or(text_color))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn window(class: &str, is_focused: bool) -> DesktopWindow {
+        DesktopWindow {
+            is_focused,
+            title: Some(format!("{class} window")),
+            class: Some(class.to_string()),
+            workspace: Some("1".to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn rule(toml: &str) -> StyleRule {
+        StyleRule::from_table(&toml.parse().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_hint_style_rules() {
+        let mut app_config =
+            AppConfig::parse_from(["wmfocus", "--bgcolor", "black", "--font", "Mono:40"]);
+        app_config.styles = vec![
+            rule(
+                r#"match = "class=^firefox$"
+                    bgcolor = "orange"
+                    font-size = 60"#,
+            ),
+            rule(
+                r#"match = ["focused", "title=urgent"]
+                    border-width = 2
+                    border-color = "red""#,
+            ),
+        ];
+
+        let style = hint_style(&app_config, &window("foot", false));
+        assert_eq!(style.bg_color, (0.0, 0.0, 0.0, 1.0));
+        assert_eq!(style.font_size, 40.0);
+        assert_eq!(style.border, None);
+
+        let style = hint_style(&app_config, &window("firefox", false));
+        assert_eq!(style.bg_color, (1.0, 165.0 / 255.0, 0.0, 1.0));
+        assert_eq!(style.text_color, app_config.text_color);
+        assert_eq!(style.font_size, 60.0);
+        assert_eq!(style.border, None);
+
+        let style = hint_style(&app_config, &window("firefox", true));
+        assert_eq!(style.text_color, app_config.text_color_current);
+        assert_eq!(style.border, Some((2.0, (1.0, 0.0, 0.0, 1.0))));
+
+        let mut urgent = window("foot", false);
+        urgent.is_urgent = true;
+        let style = hint_style(&app_config, &urgent);
+        assert_eq!(style.bg_color, app_config.bg_color_urgent);
+    }
+
+    #[test]
+    fn test_style_rule_errors() {
+        let err = |toml: &str| {
+            format!(
+                "{:#}",
+                StyleRule::from_table(&toml.parse().unwrap()).unwrap_err()
+            )
+        };
+        assert!(err(r#"bgcolor = "nope""#).contains("'bgcolor'"));
+        assert!(err(r#"match = "colour=red""#).contains("'match'"));
+        assert!(err("font-size = -1").contains("'font-size'"));
+        assert!(err("colour = 1").contains("unknown key 'colour'"));
+    }
+}
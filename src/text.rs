@@ -0,0 +1,115 @@
+use anyhow::{Context, Result};
+use pangocairo::pango::{self, IsAttribute};
+
+/// RGBA color with channels from 0 to 1.
+pub type Color = (f64, f64, f64, f64);
+
+/// `font` at `size` pixels.
+///
+/// Sizes are absolute so that hints come out the same size on every output, no matter its DPI.
+fn sized_font(font: &pango::FontDescription, size: f64) -> pango::FontDescription {
+    let mut font = font.clone();
+    font.set_absolute_size(size * f64::from(pango::SCALE));
+    font
+}
+
+fn create_layout(
+    cairo_context: &cairo::Context,
+    text: &str,
+    font: &pango::FontDescription,
+    size: f64,
+) -> pango::Layout {
+    let layout = pangocairo::functions::create_layout(cairo_context);
+    layout.set_font_description(Some(&sized_font(font, size)));
+    layout.set_text(text);
+    layout
+}
+
+/// Measure `text` like `cairo::Context::text_extents` would.
+///
+/// The extents are relative to the start of the baseline so that hint boxes can be laid out
+/// independently of the text backend.
+pub fn extents_for_text(
+    text: &str,
+    font: &pango::FontDescription,
+    size: f64,
+) -> Result<cairo::TextExtents> {
+    // Layouts are only measured, so the surface doesn't need more than a single pixel.
+    let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, 1, 1)
+        .context("Couldn't create ImageSurface")?;
+    let cr = cairo::Context::new(&surface).context("Couldn't create Cairo Surface")?;
+    let layout = create_layout(&cr, text, font, size);
+
+    let scale = f64::from(pango::SCALE);
+    let (ink, logical) = layout.extents();
+    let baseline = f64::from(layout.baseline());
+    Ok(cairo::TextExtents::new(
+        f64::from(ink.x()) / scale,
+        (f64::from(ink.y()) - baseline) / scale,
+        f64::from(ink.width()) / scale,
+        f64::from(ink.height()) / scale,
+        f64::from(logical.width()) / scale,
+        0.0,
+    ))
+}
+
+fn color_attrs(color: Color, start: usize, end: usize) -> [pango::Attribute; 2] {
+    let channel = |c: f64| (c.clamp(0.0, 1.0) * f64::from(u16::MAX)).round() as u16;
+    let mut foreground =
+        pango::AttrColor::new_foreground(channel(color.0), channel(color.1), channel(color.2))
+            .upcast();
+    // Pango treats an alpha of 0 as unset.
+    let mut alpha = pango::AttrInt::new_foreground_alpha(channel(color.3).max(1)).upcast();
+    for attr in [&mut foreground, &mut alpha] {
+        attr.set_start_index(start as u32);
+        attr.set_end_index(end as u32);
+    }
+    [foreground, alpha]
+}
+
+/// Draw `text` with the start of its baseline at `pos`.
+///
+/// The first `highlighted` bytes are drawn in `highlight_color`, the rest in `color`. Characters
+/// that are missing from `font` are taken from fallback fonts by fontconfig.
+pub fn draw_text(
+    cairo_context: &cairo::Context,
+    text: &str,
+    font: &pango::FontDescription,
+    size: f64,
+    pos: (f64, f64),
+    highlighted: usize,
+    (color, highlight_color): (Color, Color),
+) -> Result<()> {
+    let layout = create_layout(cairo_context, text, font, size);
+    let attrs = pango::AttrList::new();
+    for attr in color_attrs(highlight_color, 0, highlighted)
+        .into_iter()
+        .chain(color_attrs(color, highlighted, text.len()))
+    {
+        attrs.insert(attr);
+    }
+    layout.set_attributes(Some(&attrs));
+
+    let baseline = f64::from(layout.baseline()) / f64::from(pango::SCALE);
+    cairo_context.move_to(pos.0, pos.1 - baseline);
+    pangocairo::functions::show_layout(cairo_context, &layout);
+    cairo_context.status().context("Couldn't show text")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extents_for_text() {
+        let font = pango::FontDescription::from_string("Mono");
+        let extents = extents_for_text("sa", &font, 40.0).unwrap();
+        assert!(extents.width() > 0.0 && extents.width() <= extents.x_advance());
+        // The glyphs sit on the baseline.
+        assert!(extents.y_bearing() < 0.0);
+        assert!(extents.y_bearing() + extents.height() <= 1.0);
+
+        let larger = extents_for_text("sa", &font, 80.0).unwrap();
+        assert!(larger.x_advance() > 1.9 * extents.x_advance());
+    }
+}
@@ -1,5 +1,3 @@
-use std::iter;
-
 #[cfg(feature = "i3")]
 use std::thread::sleep;
 
@@ -8,15 +6,10 @@ use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
 
-#[cfg(feature = "i3")]
 use anyhow::bail;
 use itertools::Itertools;
-
-#[cfg(feature = "i3")]
-use log::debug;
-
-#[cfg(feature = "i3")]
-use regex::Regex;
+use pangocairo::pango;
+use serde::Serialize;
 
 #[cfg(feature = "i3")]
 use x11rb::connection::Connection;
@@ -29,44 +22,104 @@ use x11rb::protocol::xproto::{
 #[cfg(feature = "i3")]
 use x11rb::protocol::Event;
 
-#[cfg(feature = "i3")]
-use crate::args::AppConfig;
-
-use crate::DesktopWindow;
+use crate::args::{AppConfig, Backend, HintPriority, Label};
+use crate::icons;
+use crate::style::{self, HintStyle};
+use crate::text;
+use crate::wm::SCRATCHPAD_WORKSPACE;
+use crate::{DesktopOutput, DesktopWindow, DesktopWorkspace, HintKind};
 
 #[cfg(feature = "i3")]
 use crate::RenderWindow;
 
-/// Given a list of `current_hints` and a bunch of `hint_chars`, this finds a unique combination
-/// of characters that doesn't yet exist in `current_hints`. `max_count` is the maximum possible
-/// number of hints we need.
-pub fn get_next_hint(
-    current_hints: Vec<&String>,
+/// Generate `count` distinct hints from `hint_chars`.
+///
+/// The hints form a prefix-free code, so no hint is the beginning of another one and a window can
+/// be selected as soon as its hint was typed. As many hints as possible are a single character,
+/// only the overflow gets longer ones. The hints are ordered from shortest to longest and then by
+/// the order of `hint_chars`.
+pub fn generate_hints(hint_chars: &str, count: usize) -> Result<Vec<String>> {
+    let chars: Vec<char> = hint_chars.chars().unique().collect();
+    if chars.is_empty() {
+        bail!("No hint_chars found");
+    }
+    if chars.len() == 1 && count > 1 {
+        bail!("Need at least two different hint_chars for {count} hints");
+    }
+
+    // Start with all single chars and keep replacing the last of the shortest hints with all of
+    // its extensions by one char until there are enough.
+    let mut hints: Vec<Vec<usize>> = (0..chars.len()).map(|i| vec![i]).collect();
+    while hints.len() < count {
+        let shortest = hints.iter().map(Vec::len).min().unwrap_or_default();
+        let index = hints.iter().rposition(|h| h.len() == shortest).unwrap();
+        let prefix = hints.remove(index);
+        hints.extend((0..chars.len()).map(|i| {
+            let mut hint = prefix.clone();
+            hint.push(i);
+            hint
+        }));
+    }
+    hints.truncate(count);
+    hints.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+
+    Ok(hints
+        .into_iter()
+        .map(|hint| hint.into_iter().map(|i| chars[i]).collect())
+        .collect())
+}
+
+/// Assign a hint to each of `windows`, the windows that come first by `priority` get the shortest
+/// ones. Urgent windows always come first.
+///
+/// The returned hints are in the same order as `windows`.
+pub fn assign_hints(
+    windows: &[DesktopWindow],
     hint_chars: &str,
-    max_count: usize,
-) -> Result<String> {
-    // Figure out which size we need.
-    let mut size_required = 1;
-    while hint_chars.len().pow(size_required) < max_count {
-        size_required += 1;
-    }
-    let mut ret = hint_chars
-        .chars()
-        .next()
-        .context("No hint_chars found")?
-        .to_string();
-    let it = iter::repeat(hint_chars.chars().rev())
-        .take(size_required as usize)
-        .multi_cartesian_product();
-    for c in it {
-        let folded = c.into_iter().collect();
-        if !current_hints.contains(&&folded) {
-            ret = folded;
+    priority: HintPriority,
+    focused_window: Option<&DesktopWindow>,
+) -> Result<Vec<String>> {
+    let center = |w: &DesktopWindow| {
+        (
+            i64::from(w.pos.0) + i64::from(w.size.0) / 2,
+            i64::from(w.pos.1) + i64::from(w.size.1) / 2,
+        )
+    };
+
+    let mut order: Vec<usize> = (0..windows.len()).collect();
+    match (priority, focused_window) {
+        (HintPriority::Position, _) | (HintPriority::Distance, None) => {}
+        // The focused window goes last, there's little point in selecting it.
+        (HintPriority::Mru, _) => order.sort_by_key(|&i| match windows[i].focus_history {
+            Some(0) => (2, 0),
+            Some(n) => (0, n),
+            None => (1, 0),
+        }),
+        (HintPriority::Distance, Some(focused_window)) => {
+            let focused_center = center(focused_window);
+            order.sort_by_key(|&i| {
+                let c = center(&windows[i]);
+                (c.0 - focused_center.0).pow(2) + (c.1 - focused_center.1).pow(2)
+            });
         }
     }
-    #[cfg(feature = "i3")]
-    debug!("Returning next hint: {}", ret);
-    Ok(ret)
+    // Urgent windows want attention, so they get the shortest hints whatever the priority, the
+    // one that became urgent last first.
+    order.sort_by_key(|&i| {
+        (
+            !windows[i].is_urgent,
+            windows[i].urgency_history.unwrap_or(usize::MAX),
+        )
+    });
+
+    let mut hints = vec![String::new(); windows.len()];
+    for (i, hint) in order
+        .into_iter()
+        .zip(generate_hints(hint_chars, windows.len())?)
+    {
+        hints[i] = hint;
+    }
+    Ok(hints)
 }
 
 /// A rust version of XCB's `xcb_visualtype_t` struct. This is used in a FFI-way.
@@ -115,100 +168,58 @@ pub fn find_xcb_visualtype(conn: &impl Connection, visual_id: u32) -> Option<xcb
     None
 }
 
-#[cfg(feature = "i3")]
-pub fn extents_for_text(text: &str, family: &str, size: f64) -> Result<cairo::TextExtents> {
-    // Create a buffer image that should be large enough.
-    // TODO: Figure out the maximum size from the largest window on the desktop.
-    // For now we'll use made-up maximum values.
-    let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, 1024, 1024)
-        .context("Couldn't create ImageSurface")?;
-    let cr = cairo::Context::new(&surface).context("Couldn't create Cairo Surface")?;
-    cr.select_font_face(family, cairo::FontSlant::Normal, cairo::FontWeight::Normal);
-    cr.set_font_size(size);
-    cr.text_extents(text).context("Couldn't create TextExtents")
-}
-
-/// Draw a `text` onto `rw`. In case any `current_hints` are already typed, it will draw those in a
-/// different color to show that they were in fact typed.
+/// Draw the content of `rw` onto it. In case any `current_hints` are already typed, it will draw
+/// those in a different color to show that they were in fact typed.
 #[cfg(feature = "i3")]
 pub fn draw_hint_text(
     rw: &RenderWindow,
     app_config: &AppConfig,
-    text: &str,
     current_hints: &str,
 ) -> Result<()> {
+    let text = &rw.content.text;
+    let style = &rw.content.style;
     // Paint background.
     rw.cairo_context.set_operator(cairo::Operator::Source);
-
-    if rw.desktop_window.is_focused {
-        rw.cairo_context.set_source_rgb(
-            app_config.bg_color_current.0,
-            app_config.bg_color_current.1,
-            app_config.bg_color_current.2,
-        );
-    } else {
-        rw.cairo_context.set_source_rgb(
-            app_config.bg_color.0,
-            app_config.bg_color.1,
-            app_config.bg_color.2,
-        );
-    }
+    rw.cairo_context
+        .set_source_rgb(style.bg_color.0, style.bg_color.1, style.bg_color.2);
     rw.cairo_context.paint().context("Error trying to draw")?;
     rw.cairo_context.set_operator(cairo::Operator::Over);
 
-    rw.cairo_context.select_font_face(
-        &app_config.font.font_family,
-        cairo::FontSlant::Normal,
-        cairo::FontWeight::Normal,
-    );
-    rw.cairo_context.set_font_size(app_config.font.font_size);
-    rw.cairo_context.move_to(rw.draw_pos.0, rw.draw_pos.1);
-    if text.starts_with(current_hints) {
-        // Paint already selected chars.
-        if rw.desktop_window.is_focused {
-            rw.cairo_context.set_source_rgba(
-                app_config.text_color_current_alt.0,
-                app_config.text_color_current_alt.1,
-                app_config.text_color_current_alt.2,
-                app_config.text_color_current_alt.3,
-            );
-        } else {
-            rw.cairo_context.set_source_rgba(
-                app_config.text_color_alt.0,
-                app_config.text_color_alt.1,
-                app_config.text_color_alt.2,
-                app_config.text_color_alt.3,
-            );
-        }
-        for c in current_hints.chars() {
-            rw.cairo_context
-                .show_text(&c.to_string())
-                .context("Couldn't display text")?;
-        }
+    if let Some((width, color)) = style.border {
+        // The window is the box, so the border goes along its inner edge.
+        rw.cairo_context
+            .set_source_rgba(color.0, color.1, color.2, color.3);
+        rw.cairo_context.set_line_width(width);
+        rw.cairo_context.rectangle(
+            width / 2.0,
+            width / 2.0,
+            f64::from(rw.rect.2) - width,
+            f64::from(rw.rect.3) - width,
+        );
+        rw.cairo_context.stroke().context("Couldn't draw border")?;
     }
 
-    // Paint unselected chars.
-    if rw.desktop_window.is_focused {
-        rw.cairo_context.set_source_rgba(
-            app_config.text_color_current.0,
-            app_config.text_color_current.1,
-            app_config.text_color_current.2,
-            app_config.text_color_current.3,
-        );
-    } else {
-        rw.cairo_context.set_source_rgba(
-            app_config.text_color.0,
-            app_config.text_color.1,
-            app_config.text_color.2,
-            app_config.text_color.3,
-        );
+    if let HintKind::Workspace(miniature) = &rw.desktop_window.kind {
+        draw_miniature(
+            &rw.cairo_context,
+            miniature,
+            (0.0, 0.0, f64::from(rw.rect.2), f64::from(rw.rect.3)),
+            style.text_color_alt,
+        )?;
     }
-    let re = Regex::new(&format!("^{current_hints}")).unwrap();
-    for c in re.replace(text, "").chars() {
-        rw.cairo_context
-            .show_text(&c.to_string())
-            .context("Couldn't show text")?;
+
+    if let Some(icon) = &rw.content.icon {
+        draw_icon(&rw.cairo_context, icon, rw.draw_pos)?;
     }
+    text::draw_text(
+        &rw.cairo_context,
+        text,
+        &app_config.font.description,
+        style.font_size,
+        rw.draw_pos,
+        typed_len(text, current_hints),
+        (style.text_color, style.text_color_alt),
+    )?;
     rw.cairo_context.target().flush();
 
     Ok(())
@@ -286,29 +297,446 @@ pub fn sort_by_pos(mut dws: Vec<DesktopWindow>) -> Vec<DesktopWindow> {
     dws
 }
 
-/// Returns true if `r1` and `r2` overlap.
-#[cfg(feature = "i3")]
-fn intersects(r1: (i32, i32, i32, i32), r2: (i32, i32, i32, i32)) -> bool {
-    let left_corner_inside = r1.0 < r2.0 + r2.2;
-    let right_corner_inside = r1.0 + r1.2 > r2.0;
-    let top_corner_inside = r1.1 < r2.1 + r2.3;
-    let bottom_corner_inside = r1.1 + r1.3 > r2.1;
-    left_corner_inside && right_corner_inside && top_corner_inside && bottom_corner_inside
+/// Font size of the overview entries relative to the hint font size.
+const OVERVIEW_FONT_SCALE: f64 = 0.5;
+
+/// Font size of output hints relative to the hint font size.
+const OUTPUT_FONT_SCALE: f64 = 2.0;
+
+/// Maximum number of characters of an overview label.
+const OVERVIEW_LABEL_LENGTH: usize = 32;
+
+/// Font size to draw the hint of `window` with.
+///
+/// Windows on hidden workspaces and workspace tiles are shown in the overview grid which uses a
+/// smaller font so that their labels fit. Output hints are larger since there are only a few.
+pub fn hint_font_size(font_size: f64, window: &DesktopWindow) -> f64 {
+    match window.kind {
+        HintKind::Window if window.is_visible => font_size,
+        HintKind::Output => font_size * OUTPUT_FONT_SCALE,
+        _ => font_size * OVERVIEW_FONT_SCALE,
+    }
 }
 
-/// Finds overlaps and returns a list of those rects in the format (x, y, w, h).
-#[cfg(feature = "i3")]
-pub fn find_overlaps(
-    rws: Vec<&RenderWindow>,
-    rect: (i32, i32, i32, i32),
-) -> Vec<(i32, i32, i32, i32)> {
-    let mut overlaps = vec![];
-    for rw in rws {
-        if intersects(rw.rect, rect) {
-            overlaps.push(rw.rect);
+/// Text to draw for `hint`.
+///
+/// Windows on hidden workspaces don't show their content on screen so their hint is followed by
+/// the workspace, class and title. Workspace and output tiles show their name.
+pub fn hint_text(hint: &str, window: &DesktopWindow) -> String {
+    match window.kind {
+        HintKind::Workspace(_) => {
+            return format!("{hint} {}", window.workspace.as_deref().unwrap_or_default());
+        }
+        HintKind::Output => {
+            return format!("{hint} {}", window.output.as_deref().unwrap_or_default());
         }
+        HintKind::Window if window.is_visible => return hint.to_string(),
+        HintKind::Window => {}
+    }
+    let workspace = match window.workspace.as_deref() {
+        Some(SCRATCHPAD_WORKSPACE) => "scratchpad",
+        Some(workspace) => workspace,
+        None => "?",
+    };
+    let mut label = format!(
+        "[{workspace}] {}",
+        window.class.as_deref().unwrap_or_default()
+    );
+    if let Some(title) = &window.title {
+        label = format!("{label}: {title}");
+    }
+    if label.chars().count() > OVERVIEW_LABEL_LENGTH {
+        label = label.chars().take(OVERVIEW_LABEL_LENGTH - 1).collect();
+        label.push('…');
+    }
+    format!("{hint} {label}")
+}
+
+/// Number of bytes at the start of `text` that were already typed as `pressed_keys`.
+pub fn typed_len(text: &str, pressed_keys: &str) -> usize {
+    if text.starts_with(pressed_keys) {
+        pressed_keys.len()
+    } else {
+        0
     }
-    overlaps
+}
+
+/// Space between the icon and the text relative to the hint font size.
+const ICON_GAP_SCALE: f64 = 0.25;
+
+/// Icon drawn in front of the hint text.
+#[derive(Debug, Clone)]
+pub struct HintIcon {
+    pub surface: cairo::ImageSurface,
+    /// Edge length of the square the icon is scaled into.
+    pub size: f64,
+    /// Top left corner of the icon relative to the text cursor.
+    pub offset: (f64, f64),
+}
+
+/// Everything that is drawn inside of a hint box.
+#[derive(Debug, Clone)]
+pub struct HintContent {
+    pub text: String,
+    pub icon: Option<HintIcon>,
+    /// Extents of the text and the icon combined, relative to the text cursor.
+    pub extents: cairo::TextExtents,
+    pub style: HintStyle,
+}
+
+/// The `label` of `window`, if it has one.
+pub fn window_label(label: Label, window: &DesktopWindow) -> Option<String> {
+    let title = window.title.as_deref().filter(|t| !t.is_empty());
+    let class = window.class.as_deref().filter(|c| !c.is_empty());
+    match (label, class, title) {
+        (Label::Title, _, title) => title.map(str::to_string),
+        (Label::Class, class, _) => class.map(str::to_string),
+        (Label::Both, Some(class), Some(title)) => Some(format!("{class}: {title}")),
+        (Label::Both, class, title) => class.or(title).map(str::to_string),
+    }
+}
+
+/// Append as much of `text` to `prefix` as fits into `max_width` pixels.
+///
+/// Truncated text ends in an ellipsis. If not even a single character fits, only the trimmed
+/// `prefix` is returned.
+pub fn truncate_to_width(
+    prefix: &str,
+    text: &str,
+    max_width: f64,
+    font: &pango::FontDescription,
+    size: f64,
+) -> Result<String> {
+    let width = |s: &str| text::extents_for_text(s, font, size).map(|e| e.x_advance());
+    let full = format!("{prefix}{text}");
+    if width(&full)? <= max_width {
+        return Ok(full);
+    }
+
+    // Binary search for the longest number of chars that still fits with the ellipsis.
+    let chars: Vec<char> = text.chars().collect();
+    let (mut fits, mut too_long) = (0, chars.len());
+    while too_long - fits > 1 {
+        let mid = (fits + too_long) / 2;
+        let candidate: String = chars[..mid].iter().collect();
+        if width(&format!("{prefix}{}…", candidate.trim_end()))? <= max_width {
+            fits = mid;
+        } else {
+            too_long = mid;
+        }
+    }
+    if fits == 0 {
+        return Ok(prefix.trim_end().to_string());
+    }
+    let truncated: String = chars[..fits].iter().collect();
+    Ok(format!("{prefix}{}…", truncated.trim_end()))
+}
+
+/// Assemble what to draw for `hint` on `window` and measure it.
+///
+/// Visible windows get the `--label` next to their hint, truncated to the width of the window,
+/// and with `--icons` the icon of their class in front of it. The text is measured in the font
+/// size of the window's style.
+pub fn hint_content(
+    app_config: &AppConfig,
+    hint: &str,
+    window: &DesktopWindow,
+) -> Result<HintContent> {
+    let style = style::hint_style(app_config, window);
+    let font = &app_config.font.description;
+    let size = style.font_size;
+    let is_window = matches!(window.kind, HintKind::Window) && window.is_visible;
+
+    let icon = if is_window && app_config.icons {
+        window
+            .class
+            .as_deref()
+            .and_then(|class| icons::load_icon(class, size, app_config.icon_theme.as_deref()))
+    } else {
+        None
+    };
+    let icon_space = if icon.is_some() {
+        size * (1.0 + ICON_GAP_SCALE)
+    } else {
+        0.0
+    };
+
+    let text = match app_config.label.filter(|_| is_window) {
+        Some(label) => match window_label(label, window) {
+            Some(label) => {
+                let max_width =
+                    f64::from(window.size.0) / (1.0 + f64::from(app_config.margin)) - icon_space;
+                truncate_to_width(&format!("{hint} "), &label, max_width, font, size)?
+            }
+            None => hint.to_string(),
+        },
+        None => hint_text(hint, window),
+    };
+    let text_extents = text::extents_for_text(&text, font, size)?;
+
+    let Some(surface) = icon else {
+        return Ok(HintContent {
+            text,
+            icon: None,
+            extents: text_extents,
+            style,
+        });
+    };
+    let height = text_extents.height().max(size);
+    let y_bearing = text_extents.y_bearing() - (height - text_extents.height()) / 2.0;
+    let extents = cairo::TextExtents::new(
+        text_extents.x_bearing() - icon_space,
+        y_bearing,
+        text_extents.width() + icon_space,
+        height,
+        text_extents.x_advance() + icon_space,
+        text_extents.y_advance(),
+    );
+    Ok(HintContent {
+        text,
+        icon: Some(HintIcon {
+            surface,
+            size,
+            offset: (
+                text_extents.x_bearing() - icon_space,
+                y_bearing + (height - size) / 2.0,
+            ),
+        }),
+        extents,
+        style,
+    })
+}
+
+/// Draw `icon` relative to the text cursor at `pos`.
+pub fn draw_icon(cairo_context: &cairo::Context, icon: &HintIcon, pos: (f64, f64)) -> Result<()> {
+    let scale = icon.size / f64::from(icon.surface.width().max(icon.surface.height()));
+    cairo_context.save()?;
+    cairo_context.translate(pos.0 + icon.offset.0, pos.1 + icon.offset.1);
+    cairo_context.scale(scale, scale);
+    cairo_context.set_source_surface(&icon.surface, 0.0, 0.0)?;
+    cairo_context.paint().context("Couldn't draw icon")?;
+    cairo_context.restore()?;
+    Ok(())
+}
+
+/// Lay out all windows on hidden workspaces in a grid inside of `area` (x, y, w, h).
+///
+/// This replaces the geometry of the hidden windows, so it's only meant for placing their hints.
+/// Windows are grouped by workspace and fill the grid column by column so that it reads like a
+/// list. Every row is twice as high as the overview font and more columns are added as soon as a
+/// single column doesn't fit into `area` anymore.
+pub fn arrange_hidden_windows(
+    windows: &mut [DesktopWindow],
+    area: (i32, i32, i32, i32),
+    font_size: f64,
+) {
+    let mut hidden: Vec<&mut DesktopWindow> =
+        windows.iter_mut().filter(|w| !w.is_visible).collect();
+    if hidden.is_empty() {
+        return;
+    }
+    hidden.sort_by(|a, b| a.workspace.cmp(&b.workspace));
+
+    let row_height = (font_size * OVERVIEW_FONT_SCALE * 2.0).ceil() as i32;
+    let rows_per_column = (area.3 / row_height).max(1) as usize;
+    let columns = hidden.len().div_ceil(rows_per_column);
+    let rows = hidden.len().div_ceil(columns);
+    let cell_width = area.2 / columns as i32;
+    let top = area.1 + (area.3 - rows as i32 * row_height).max(0) / 2;
+    for (i, window) in hidden.into_iter().enumerate() {
+        let (column, row) = ((i / rows) as i32, (i % rows) as i32);
+        window.pos = (area.0 + column * cell_width, top + row * row_height);
+        window.size = (cell_width, row_height);
+    }
+}
+
+/// Share of a grid cell that a workspace tile takes up.
+const TILE_SCALE: f64 = 0.9;
+
+/// Turn `workspaces` into tiles for `--target workspaces`, laid out in a grid inside of `area`.
+///
+/// Every tile keeps the aspect ratio of its workspace and carries a miniature of the `windows` on
+/// it. Tiles are identified by their index in `workspaces`.
+pub fn workspace_tiles(
+    workspaces: &[DesktopWorkspace],
+    windows: &[DesktopWindow],
+    area: (i32, i32, i32, i32),
+) -> Vec<DesktopWindow> {
+    if workspaces.is_empty() {
+        return vec![];
+    }
+    let columns = (workspaces.len() as f64).sqrt().ceil() as usize;
+    let rows = workspaces.len().div_ceil(columns);
+    let (cell_width, cell_height) = (area.2 / columns as i32, area.3 / rows as i32);
+
+    workspaces
+        .iter()
+        .enumerate()
+        .map(|(i, workspace)| {
+            let workspace_size = (
+                f64::from(workspace.size.0.max(1)),
+                f64::from(workspace.size.1.max(1)),
+            );
+            let scale = (f64::from(cell_width) / workspace_size.0)
+                .min(f64::from(cell_height) / workspace_size.1)
+                * TILE_SCALE;
+            let size = (
+                (workspace_size.0 * scale) as i32,
+                (workspace_size.1 * scale) as i32,
+            );
+            let (column, row) = ((i % columns) as i32, (i / columns) as i32);
+            let pos = (
+                area.0 + column * cell_width + (cell_width - size.0) / 2,
+                area.1 + row * cell_height + (cell_height - size.1) / 2,
+            );
+
+            let miniature = windows
+                .iter()
+                .filter(|w| w.workspace.as_ref() == Some(&workspace.name))
+                .map(|w| {
+                    (
+                        f64::from(w.pos.0 - workspace.pos.0) / workspace_size.0,
+                        f64::from(w.pos.1 - workspace.pos.1) / workspace_size.1,
+                        f64::from(w.size.0) / workspace_size.0,
+                        f64::from(w.size.1) / workspace_size.1,
+                    )
+                })
+                .collect();
+
+            DesktopWindow {
+                id: i as i64,
+                x_window_id: None,
+                address: None,
+                pos,
+                size,
+                is_focused: workspace.is_focused,
+                title: None,
+                class: None,
+                workspace: Some(workspace.name.clone()),
+                output: workspace.output.clone(),
+                is_floating: false,
+                is_fullscreen: false,
+                is_urgent: false,
+                urgency_history: None,
+                is_visible: true,
+                focus_history: None,
+                kind: HintKind::Workspace(miniature),
+            }
+        })
+        .collect()
+}
+
+/// Turn `desktop_outputs` into tiles for `--target outputs`.
+///
+/// Tiles cover their whole output and are identified by their index in `desktop_outputs`.
+pub fn output_tiles(
+    desktop_outputs: &[DesktopOutput],
+    focused_output: Option<&str>,
+) -> Vec<DesktopWindow> {
+    desktop_outputs
+        .iter()
+        .enumerate()
+        .map(|(i, output)| DesktopWindow {
+            id: i as i64,
+            x_window_id: None,
+            address: None,
+            pos: output.pos,
+            size: output.size,
+            is_focused: focused_output == Some(output.name.as_str()),
+            title: None,
+            class: None,
+            workspace: None,
+            output: Some(output.name.clone()),
+            is_floating: false,
+            is_fullscreen: false,
+            is_urgent: false,
+            urgency_history: None,
+            is_visible: true,
+            focus_history: None,
+            kind: HintKind::Output,
+        })
+        .collect()
+}
+
+/// Opacity of hints and outlines that don't match the keys typed so far.
+pub const DIMMED_HINT_ALPHA: f64 = 0.3;
+
+/// Width of the window outlines drawn with `--dim`.
+const OUTLINE_WIDTH: f64 = 3.0;
+
+/// Darken everything with `dim_color` except for the `windows` that still match the typed keys.
+///
+/// Each entry of `windows` comes with the style of its hint and whether it still matches. Every
+/// window gets an outline in the lighter of its hint colors so that it stands out against the
+/// darkened desktop, outlines of windows that no longer match fade out. Only windows on visible
+/// workspaces are outlined since the others have no place on screen.
+pub fn draw_dim_overlay(
+    cairo_context: &cairo::Context,
+    dim_color: (f64, f64, f64, f64),
+    windows: &[(&DesktopWindow, &HintStyle, bool)],
+) -> Result<()> {
+    let luminance = |c: (f64, f64, f64, f64)| 0.2126 * c.0 + 0.7152 * c.1 + 0.0722 * c.2;
+
+    cairo_context.save()?;
+    cairo_context.set_operator(cairo::Operator::Source);
+    cairo_context.set_source_rgba(dim_color.0, dim_color.1, dim_color.2, dim_color.3);
+    cairo_context.paint().context("Couldn't dim the desktop")?;
+
+    cairo_context.set_line_width(OUTLINE_WIDTH);
+    for (window, style, is_match) in windows {
+        if !matches!(window.kind, HintKind::Window) || !window.is_visible {
+            continue;
+        }
+        let (x, y) = (f64::from(window.pos.0), f64::from(window.pos.1));
+        let (width, height) = (f64::from(window.size.0), f64::from(window.size.1));
+
+        if *is_match {
+            cairo_context.set_operator(cairo::Operator::Clear);
+            cairo_context.rectangle(x, y, width, height);
+            cairo_context.fill()?;
+        }
+
+        let color = if luminance(style.bg_color) > luminance(style.text_color) {
+            style.bg_color
+        } else {
+            style.text_color
+        };
+        let alpha = if *is_match { 1.0 } else { DIMMED_HINT_ALPHA };
+        cairo_context.set_operator(cairo::Operator::Over);
+        cairo_context.set_source_rgba(color.0, color.1, color.2, alpha);
+        // Keep the outline inside of the window so that neighbours don't overlap.
+        cairo_context.rectangle(
+            x + OUTLINE_WIDTH / 2.0,
+            y + OUTLINE_WIDTH / 2.0,
+            width - OUTLINE_WIDTH,
+            height - OUTLINE_WIDTH,
+        );
+        cairo_context.stroke()?;
+    }
+    cairo_context.restore()?;
+    Ok(())
+}
+
+/// Draw the outlines of the windows in `miniature` into `rect` (x, y, w, h).
+pub fn draw_miniature(
+    cairo_context: &cairo::Context,
+    miniature: &[(f64, f64, f64, f64)],
+    rect: (f64, f64, f64, f64),
+    color: (f64, f64, f64, f64),
+) -> Result<()> {
+    cairo_context.set_source_rgba(color.0, color.1, color.2, color.3);
+    cairo_context.set_line_width(2.0);
+    for (x, y, w, h) in miniature {
+        // Inset the outlines a little so that neighbouring windows don't merge.
+        cairo_context.rectangle(
+            rect.0 + x * rect.2 + 2.0,
+            rect.1 + y * rect.3 + 2.0,
+            (w * rect.2 - 4.0).max(1.0),
+            (h * rect.3 - 4.0).max(1.0),
+        );
+    }
+    cairo_context.stroke().context("Couldn't draw miniature")?;
+    Ok(())
 }
 
 /// Remove last pressed key from pressed keys
@@ -343,6 +771,108 @@ pub fn get_pressed_symbol(conn: &impl Connection, event: Event) -> xkeysym::Keys
     }
 }
 
+/// The selected window as printed by `--output json`.
+#[derive(Serialize, Debug)]
+pub struct SelectedWindow<'a> {
+    backend: Backend,
+    id: i64,
+    x_window_id: Option<i32>,
+    address: Option<&'a str>,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    focused: bool,
+    visible: bool,
+    floating: bool,
+    fullscreen: bool,
+    title: Option<&'a str>,
+    class: Option<&'a str>,
+    workspace: Option<&'a str>,
+    output: Option<&'a str>,
+    hint: &'a str,
+}
+
+impl<'a> SelectedWindow<'a> {
+    pub fn new(backend: Backend, hint: &'a str, window: &'a DesktopWindow) -> SelectedWindow<'a> {
+        SelectedWindow {
+            backend,
+            id: window.id,
+            x_window_id: window.x_window_id,
+            address: window.address.as_deref(),
+            x: window.pos.0,
+            y: window.pos.1,
+            width: window.size.0,
+            height: window.size.1,
+            focused: window.is_focused,
+            visible: window.is_visible,
+            floating: window.is_floating,
+            fullscreen: window.is_fullscreen,
+            title: window.title.as_deref(),
+            class: window.class.as_deref(),
+            workspace: window.workspace.as_deref(),
+            output: window.output.as_deref(),
+            hint,
+        }
+    }
+}
+
+/// The selected workspace as printed by `--target workspaces --output json`.
+#[derive(Serialize, Debug)]
+pub struct SelectedWorkspace<'a> {
+    backend: Backend,
+    id: i64,
+    name: &'a str,
+    output: Option<&'a str>,
+    focused: bool,
+    visible: bool,
+    hint: &'a str,
+}
+
+impl<'a> SelectedWorkspace<'a> {
+    pub fn new(
+        backend: Backend,
+        hint: &'a str,
+        workspace: &'a DesktopWorkspace,
+    ) -> SelectedWorkspace<'a> {
+        SelectedWorkspace {
+            backend,
+            id: workspace.id,
+            name: &workspace.name,
+            output: workspace.output.as_deref(),
+            focused: workspace.is_focused,
+            visible: workspace.is_visible,
+            hint,
+        }
+    }
+}
+
+/// The selected output as printed by `--target outputs --output json`.
+#[derive(Serialize, Debug)]
+pub struct SelectedOutput<'a> {
+    backend: Backend,
+    name: &'a str,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    hint: &'a str,
+}
+
+impl<'a> SelectedOutput<'a> {
+    pub fn new(backend: Backend, hint: &'a str, output: &'a DesktopOutput) -> SelectedOutput<'a> {
+        SelectedOutput {
+            backend,
+            name: &output.name,
+            x: output.pos.0,
+            y: output.pos.1,
+            width: output.size.0,
+            height: output.size.1,
+            hint,
+        }
+    }
+}
+
 /// Struct helps to write sequence and check if it is found in list of exit sequences
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Sequence {
@@ -397,13 +927,279 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_intersects() {
-        assert!(intersects((1905, 705, 31, 82), (1905, 723, 38, 64)));
+    fn test_generate_hints() {
+        assert_eq!(generate_hints("asd", 3).unwrap(), ["a", "s", "d"]);
+        assert_eq!(
+            generate_hints("asd", 5).unwrap(),
+            ["a", "s", "da", "ds", "dd"]
+        );
+        assert_eq!(
+            generate_hints("asd", 7).unwrap(),
+            ["a", "sa", "ss", "sd", "da", "ds", "dd"]
+        );
+        assert_eq!(generate_hints("aas", 2).unwrap(), ["a", "s"]);
+        assert!(generate_hints("", 1).is_err());
+        assert!(generate_hints("a", 2).is_err());
+        assert_eq!(generate_hints("a", 1).unwrap(), ["a"]);
+    }
+
+    /// Between 2 and 12 distinct hint chars.
+    fn hint_chars() -> impl proptest::strategy::Strategy<Value = String> {
+        use proptest::strategy::Strategy;
+
+        proptest::sample::subsequence(('a'..='z').collect::<Vec<_>>(), 2..=12)
+            .prop_shuffle()
+            .prop_map(|chars| chars.into_iter().collect())
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn test_generated_hints_are_prefix_free(
+            hint_chars in hint_chars(),
+            count in 0usize..600,
+        ) {
+            let hints = generate_hints(&hint_chars, count).unwrap();
+            proptest::prop_assert_eq!(hints.len(), count);
+            for (i, hint) in hints.iter().enumerate() {
+                proptest::prop_assert!(hint.chars().all(|c| hint_chars.contains(c)));
+                for (j, other) in hints.iter().enumerate() {
+                    proptest::prop_assert!(
+                        i == j || !other.starts_with(hint.as_str()),
+                        "{} is a prefix of {}", hint, other
+                    );
+                }
+            }
+        }
+
+        #[test]
+        fn test_generated_hints_are_short(hint_chars in hint_chars(), count in 0usize..600) {
+            let distinct = hint_chars.chars().unique().count();
+            let hints = generate_hints(&hint_chars, count).unwrap();
+            let lengths: Vec<_> = hints.iter().map(String::len).collect();
+            proptest::prop_assert!(lengths.windows(2).all(|w| w[0] <= w[1]));
+            if count <= distinct {
+                proptest::prop_assert!(lengths.iter().all(|l| *l == 1));
+            } else if count <= distinct * distinct {
+                // As many single chars as still leave enough two char hints for the rest.
+                let singles = lengths.iter().filter(|l| **l == 1).count();
+                proptest::prop_assert_eq!(
+                    singles,
+                    (distinct * distinct - count) / (distinct - 1)
+                );
+                proptest::prop_assert!(lengths.iter().all(|l| *l <= 2));
+            }
+        }
+    }
+
+    #[test]
+    fn test_assign_hints_by_distance() {
+        let window = |id: i64, x: i32| DesktopWindow {
+            id,
+            pos: (x, 0),
+            is_focused: id == 3,
+            ..Default::default()
+        };
+        let windows = [window(1, 0), window(2, 100), window(3, 200), window(4, 300)];
+
+        let hints = assign_hints(&windows, "asd", HintPriority::Position, Some(&windows[2]));
+        assert_eq!(hints.unwrap(), ["a", "s", "da", "ds"]);
+
+        let hints = assign_hints(&windows, "asd", HintPriority::Distance, Some(&windows[2]));
+        assert_eq!(hints.unwrap(), ["ds", "s", "a", "da"]);
+
+        let hints = assign_hints(&windows, "asd", HintPriority::Distance, None);
+        assert_eq!(hints.unwrap(), ["a", "s", "da", "ds"]);
+
+        // Urgent windows come first, the others keep their order.
+        let mut windows = windows;
+        windows[3].is_urgent = true;
+        let hints = assign_hints(&windows, "asd", HintPriority::Distance, Some(&windows[2]));
+        assert_eq!(hints.unwrap(), ["ds", "da", "s", "a"]);
+
+        // Among urgent windows, the one that became urgent last comes first.
+        windows[1].is_urgent = true;
+        windows[1].urgency_history = Some(1);
+        windows[3].urgency_history = Some(0);
+        let hints = assign_hints(&windows, "asd", HintPriority::Distance, Some(&windows[2]));
+        assert_eq!(hints.unwrap(), ["ds", "s", "da", "a"]);
+    }
+
+    #[test]
+    fn test_assign_hints_by_mru() {
+        let window = |id: i64, focus_history: Option<usize>| DesktopWindow {
+            id,
+            is_focused: focus_history == Some(0),
+            focus_history,
+            ..Default::default()
+        };
+        let windows = [
+            window(1, Some(0)),
+            window(2, None),
+            window(3, Some(2)),
+            window(4, Some(1)),
+        ];
+        let hints = assign_hints(&windows, "asd", HintPriority::Mru, Some(&windows[0]));
+        assert_eq!(hints.unwrap(), ["ds", "da", "s", "a"]);
+    }
+
+    fn hidden_window(id: i64, workspace: &str) -> DesktopWindow {
+        DesktopWindow {
+            id,
+            title: Some("Inbox - Mozilla Thunderbird".to_string()),
+            class: Some("thunderbird".to_string()),
+            workspace: Some(workspace.to_string()),
+            is_visible: false,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_hint_text_for_hidden_window() {
+        let mut window = hidden_window(1, "__i3_scratch");
+        assert_eq!(
+            hint_text("sa", &window),
+            "sa [scratchpad] thunderbird: Inbox…"
+        );
+        window.is_visible = true;
+        assert_eq!(hint_text("sa", &window), "sa");
     }
 
     #[test]
-    fn test_no_intersect() {
-        assert!(!intersects((1905, 705, 31, 82), (2000, 723, 38, 64)));
+    fn test_window_label() {
+        let mut window = hidden_window(1, "1");
+        assert_eq!(
+            window_label(Label::Title, &window).as_deref(),
+            Some("Inbox - Mozilla Thunderbird")
+        );
+        assert_eq!(
+            window_label(Label::Class, &window).as_deref(),
+            Some("thunderbird")
+        );
+        assert_eq!(
+            window_label(Label::Both, &window).as_deref(),
+            Some("thunderbird: Inbox - Mozilla Thunderbird")
+        );
+        window.title = Some(String::new());
+        assert_eq!(window_label(Label::Title, &window), None);
+        assert_eq!(
+            window_label(Label::Both, &window).as_deref(),
+            Some("thunderbird")
+        );
+    }
+
+    #[test]
+    fn test_truncate_to_width() {
+        let text = "Inbox - Mozilla Thunderbird";
+        let font = pango::FontDescription::from_string("Mono");
+        let width = |s: &str| text::extents_for_text(s, &font, 20.0).unwrap().x_advance();
+
+        let full = format!("a {text}");
+        assert_eq!(
+            truncate_to_width("a ", text, width(&full), &font, 20.0).unwrap(),
+            full
+        );
+
+        let truncated = truncate_to_width("a ", text, width(&full) / 2.0, &font, 20.0).unwrap();
+        assert!(truncated.starts_with("a Inbox"));
+        assert!(truncated.ends_with('…'));
+        assert!(width(&truncated) <= width(&full) / 2.0);
+
+        assert_eq!(
+            truncate_to_width("a ", text, width("a"), &font, 20.0).unwrap(),
+            "a"
+        );
+    }
+
+    #[test]
+    fn test_arrange_hidden_windows() {
+        let mut windows: Vec<DesktopWindow> = (0..5)
+            .map(|i| hidden_window(i, if i % 2 == 0 { "2" } else { "3" }))
+            .collect();
+        windows[4].is_visible = true;
+
+        // Two rows fit, so the four hidden windows end up in two columns.
+        arrange_hidden_windows(&mut windows, (100, 0, 1000, 250), 100.0);
+        let cells: Vec<_> = windows.iter().map(|w| (w.id, w.pos, w.size)).collect();
+        assert_eq!(
+            cells,
+            [
+                (0, (100, 25), (500, 100)),
+                (1, (600, 25), (500, 100)),
+                (2, (100, 125), (500, 100)),
+                (3, (600, 125), (500, 100)),
+                (4, (0, 0), (100, 100)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_workspace_tiles() {
+        let workspaces: Vec<DesktopWorkspace> = (1..=3)
+            .map(|i| DesktopWorkspace {
+                id: i,
+                name: i.to_string(),
+                output: Some("eDP-1".to_string()),
+                pos: (0, 0),
+                size: (1000, 500),
+                is_focused: i == 2,
+                is_visible: i == 2,
+            })
+            .collect();
+        let mut window = hidden_window(10, "3");
+        window.pos = (500, 0);
+        window.size = (500, 250);
+
+        // Three workspaces need a 2x2 grid of 500x250 cells.
+        let tiles = workspace_tiles(&workspaces, &[window], (0, 0, 1000, 500));
+        assert_eq!(tiles.len(), 3);
+        assert_eq!((tiles[0].pos, tiles[0].size), ((25, 12), (450, 225)));
+        assert_eq!((tiles[2].pos, tiles[2].size), ((25, 262), (450, 225)));
+        assert!(tiles[1].is_focused);
+        assert_eq!(tiles[0].kind, HintKind::Workspace(vec![]));
+        assert_eq!(
+            tiles[2].kind,
+            HintKind::Workspace(vec![(0.5, 0.0, 0.5, 0.5)])
+        );
+        assert_eq!(hint_text("sa", &tiles[2]), "sa 3");
+    }
+
+    #[test]
+    fn test_selected_window_json() {
+        let window = DesktopWindow {
+            id: 42,
+            address: Some("0x55d5a1b2c3d0".to_string()),
+            pos: (10, 20),
+            size: (800, 600),
+            is_focused: true,
+            title: Some("~/src/wmfocus".to_string()),
+            class: Some("foot".to_string()),
+            workspace: Some("2".to_string()),
+            output: Some("DP-1".to_string()),
+            ..Default::default()
+        };
+        let selected = SelectedWindow::new(Backend::Hyprland, "sa", &window);
+        assert_eq!(
+            serde_json::to_value(&selected).unwrap(),
+            serde_json::json!({
+                "backend": "hyprland",
+                "id": 42,
+                "x_window_id": null,
+                "address": "0x55d5a1b2c3d0",
+                "x": 10,
+                "y": 20,
+                "width": 800,
+                "height": 600,
+                "focused": true,
+                "visible": true,
+                "floating": false,
+                "fullscreen": false,
+                "title": "~/src/wmfocus",
+                "class": "foot",
+                "workspace": "2",
+                "output": "DP-1",
+                "hint": "sa",
+            })
+        );
     }
 
     #[test]
@@ -1,25 +1,40 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use log::info;
 use std::collections::HashMap;
 use std::os::fd::AsFd;
 
-use hyprland::prelude::*;
-
 use wayland_client::{
-    protocol::{wl_buffer, wl_compositor, wl_keyboard, wl_registry, wl_seat, wl_shm, wl_shm_pool, wl_surface},
     globals::{registry_queue_init, GlobalListContents},
+    protocol::{
+        wl_buffer, wl_compositor, wl_keyboard, wl_output, wl_registry, wl_seat, wl_shm,
+        wl_shm_pool, wl_surface,
+    },
     Connection, Dispatch, QueueHandle,
 };
 
+use wayland_protocols::wp::{
+    fractional_scale::v1::client::{wp_fractional_scale_manager_v1, wp_fractional_scale_v1},
+    viewporter::client::{wp_viewport, wp_viewporter},
+};
+
 use wayland_protocols_wlr::layer_shell::v1::client::{
     zwlr_layer_shell_v1::{self, Layer},
     zwlr_layer_surface_v1::{self, Anchor, KeyboardInteractivity},
 };
 
-use crate::{args::AppConfig, DesktopWindow};
+use crate::{args::AppConfig, layout, text, utils, DesktopOutput, DesktopWindow, HintKind};
 
-pub struct WaylandRenderer {
-    app_config: AppConfig,
+/// Number of buffers per output so that we can draw into one while the compositor still reads from
+/// the other.
+const BUFFER_COUNT: usize = 2;
+
+pub struct WaylandRenderer<'a> {
+    app_config: &'a AppConfig,
+    desktop_outputs: Vec<DesktopOutput>,
+    /// Where each hint goes, computed by `render_hints`.
+    placements: HashMap<String, layout::HintPlacement>,
+    /// What to draw inside of each hint box, computed by `render_hints`.
+    contents: HashMap<String, utils::HintContent>,
 }
 
 struct RenderState {
@@ -27,12 +42,56 @@ struct RenderState {
     _shm: wl_shm::WlShm,
     _layer_shell: zwlr_layer_shell_v1::ZwlrLayerShellV1,
     _seat: Option<wl_seat::WlSeat>,
-    configured: bool,
+    outputs: Vec<OutputState>,
     keyboard_state: Option<KeyboardState>,
     pressed_keys: String,
     should_exit: bool,
 }
 
+/// A `wl_output` together with what the compositor told us about it.
+struct OutputState {
+    wl_output: wl_output::WlOutput,
+    /// Only sent by `wl_output` version 4 and up.
+    name: Option<String>,
+    /// Position of the output in the global compositor space.
+    position: (i32, i32),
+    /// Size of our layer surface on this output once the compositor configured it.
+    configured_size: Option<(i32, i32)>,
+    /// Integer scale of the output.
+    scale_factor: i32,
+    /// Preferred scale of our surface on this output if the compositor supports fractional
+    /// scaling.
+    fractional_scale: Option<f64>,
+    /// Whether the compositor still holds on to each of our buffers for this output.
+    buffers_busy: [bool; BUFFER_COUNT],
+}
+
+impl OutputState {
+    /// Scale to render with on this output.
+    fn scale(&self) -> f64 {
+        self.fractional_scale
+            .unwrap_or_else(|| f64::from(self.scale_factor))
+    }
+}
+
+/// The layer surface on one output.
+struct OutputSurface {
+    surface: wl_surface::WlSurface,
+    _layer_surface: zwlr_layer_surface_v1::ZwlrLayerSurfaceV1,
+    /// Only available if the compositor supports fractional scaling.
+    viewport: Option<wp_viewport::WpViewport>,
+    _fractional_scale: Option<wp_fractional_scale_v1::WpFractionalScaleV1>,
+    /// Size of `buffers` in physical pixels.
+    buffer_size: (i32, i32),
+    buffers: Vec<ShmBuffer>,
+}
+
+/// A `wl_buffer` in shared memory that can be drawn into again once the compositor released it.
+struct ShmBuffer {
+    wl_buffer: wl_buffer::WlBuffer,
+    mmap: memmap2::MmapMut,
+}
+
 struct KeyboardState {
     xkb_context: xkbcommon::xkb::Context,
     xkb_state: Option<xkbcommon::xkb::State>,
@@ -46,7 +105,8 @@ impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for RenderState {
         _: &GlobalListContents,
         _: &Connection,
         _: &QueueHandle<Self>,
-    ) {}
+    ) {
+    }
 }
 
 impl Dispatch<wl_compositor::WlCompositor, ()> for RenderState {
@@ -57,7 +117,8 @@ impl Dispatch<wl_compositor::WlCompositor, ()> for RenderState {
         _: &(),
         _: &Connection,
         _: &QueueHandle<Self>,
-    ) {}
+    ) {
+    }
 }
 
 impl Dispatch<wl_surface::WlSurface, ()> for RenderState {
@@ -68,7 +129,8 @@ impl Dispatch<wl_surface::WlSurface, ()> for RenderState {
         _: &(),
         _: &Connection,
         _: &QueueHandle<Self>,
-    ) {}
+    ) {
+    }
 }
 
 impl Dispatch<wl_shm::WlShm, ()> for RenderState {
@@ -79,7 +141,8 @@ impl Dispatch<wl_shm::WlShm, ()> for RenderState {
         _: &(),
         _: &Connection,
         _: &QueueHandle<Self>,
-    ) {}
+    ) {
+    }
 }
 
 impl Dispatch<wl_shm_pool::WlShmPool, ()> for RenderState {
@@ -90,18 +153,23 @@ impl Dispatch<wl_shm_pool::WlShmPool, ()> for RenderState {
         _: &(),
         _: &Connection,
         _: &QueueHandle<Self>,
-    ) {}
+    ) {
+    }
 }
 
-impl Dispatch<wl_buffer::WlBuffer, ()> for RenderState {
+impl Dispatch<wl_buffer::WlBuffer, (usize, usize)> for RenderState {
     fn event(
-        _: &mut Self,
+        state: &mut Self,
         _: &wl_buffer::WlBuffer,
-        _: wl_buffer::Event,
-        _: &(),
+        event: wl_buffer::Event,
+        (output_index, buffer_index): &(usize, usize),
         _: &Connection,
         _: &QueueHandle<Self>,
-    ) {}
+    ) {
+        if let wl_buffer::Event::Release = event {
+            state.outputs[*output_index].buffers_busy[*buffer_index] = false;
+        }
+    }
 }
 
 impl Dispatch<zwlr_layer_shell_v1::ZwlrLayerShellV1, ()> for RenderState {
@@ -112,21 +180,97 @@ impl Dispatch<zwlr_layer_shell_v1::ZwlrLayerShellV1, ()> for RenderState {
         _: &(),
         _: &Connection,
         _: &QueueHandle<Self>,
-    ) {}
+    ) {
+    }
 }
 
-impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, ()> for RenderState {
+impl Dispatch<wl_output::WlOutput, usize> for RenderState {
+    fn event(
+        state: &mut Self,
+        _: &wl_output::WlOutput,
+        event: wl_output::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_output::Event::Geometry { x, y, .. } => state.outputs[*index].position = (x, y),
+            wl_output::Event::Name { name } => state.outputs[*index].name = Some(name),
+            wl_output::Event::Scale { factor } => state.outputs[*index].scale_factor = factor,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1, ()> for RenderState {
+    fn event(
+        _: &mut Self,
+        _: &wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1,
+        _: wp_fractional_scale_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<wp_fractional_scale_v1::WpFractionalScaleV1, usize> for RenderState {
+    fn event(
+        state: &mut Self,
+        _: &wp_fractional_scale_v1::WpFractionalScaleV1,
+        event: wp_fractional_scale_v1::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        // The scale is sent as a numerator over a denominator of 120.
+        if let wp_fractional_scale_v1::Event::PreferredScale { scale } = event {
+            state.outputs[*index].fractional_scale = Some(f64::from(scale) / 120.0);
+        }
+    }
+}
+
+impl Dispatch<wp_viewporter::WpViewporter, ()> for RenderState {
+    fn event(
+        _: &mut Self,
+        _: &wp_viewporter::WpViewporter,
+        _: wp_viewporter::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<wp_viewport::WpViewport, ()> for RenderState {
+    fn event(
+        _: &mut Self,
+        _: &wp_viewport::WpViewport,
+        _: wp_viewport::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, usize> for RenderState {
     fn event(
         state: &mut Self,
         layer_surface: &zwlr_layer_surface_v1::ZwlrLayerSurfaceV1,
         event: zwlr_layer_surface_v1::Event,
-        _: &(),
+        index: &usize,
         _: &Connection,
         _: &QueueHandle<Self>,
     ) {
-        if let zwlr_layer_surface_v1::Event::Configure { serial, .. } = event {
+        if let zwlr_layer_surface_v1::Event::Configure {
+            serial,
+            width,
+            height,
+        } = event
+        {
             layer_surface.ack_configure(serial);
-            state.configured = true;
+            state.outputs[*index].configured_size = Some((width as i32, height as i32));
         }
     }
 }
@@ -139,7 +283,8 @@ impl Dispatch<wl_seat::WlSeat, ()> for RenderState {
         _: &(),
         _: &Connection,
         _: &QueueHandle<Self>,
-    ) {}
+    ) {
+    }
 }
 
 impl Dispatch<wl_keyboard::WlKeyboard, ()> for RenderState {
@@ -167,7 +312,10 @@ impl Dispatch<wl_keyboard::WlKeyboard, ()> for RenderState {
                         )
                         .expect("mmap failed");
 
-                        let slice = std::slice::from_raw_parts(ptr.as_ptr() as *const u8, size as usize - 1);
+                        let slice = std::slice::from_raw_parts(
+                            ptr.as_ptr() as *const u8,
+                            size as usize - 1,
+                        );
                         let keymap_str = std::str::from_utf8_unchecked(slice);
                         let result = keymap_str.to_string();
 
@@ -189,12 +337,18 @@ impl Dispatch<wl_keyboard::WlKeyboard, ()> for RenderState {
                 }
             }
 
-            wl_keyboard::Event::Key { key, state: key_state, .. } => {
+            wl_keyboard::Event::Key {
+                key,
+                state: key_state,
+                ..
+            } => {
                 if let Some(kb_state) = &mut state.keyboard_state {
                     if let Some(xkb_state) = &mut kb_state.xkb_state {
                         let keycode = key + 8; // Wayland to xkb conversion
 
-                        if let wayland_client::WEnum::Value(wl_keyboard::KeyState::Pressed) = key_state {
+                        if let wayland_client::WEnum::Value(wl_keyboard::KeyState::Pressed) =
+                            key_state
+                        {
                             let keysym = xkb_state.key_get_one_sym(xkb::Keycode::from(keycode));
                             let keysym_name = xkb::keysym_get_name(keysym);
 
@@ -219,10 +373,23 @@ impl Dispatch<wl_keyboard::WlKeyboard, ()> for RenderState {
                 }
             }
 
-            wl_keyboard::Event::Modifiers { mods_depressed, mods_latched, mods_locked, group, .. } => {
+            wl_keyboard::Event::Modifiers {
+                mods_depressed,
+                mods_latched,
+                mods_locked,
+                group,
+                ..
+            } => {
                 if let Some(kb_state) = &mut state.keyboard_state {
                     if let Some(xkb_state) = &mut kb_state.xkb_state {
-                        xkb_state.update_mask(mods_depressed, mods_latched, mods_locked, 0, 0, group);
+                        xkb_state.update_mask(
+                            mods_depressed,
+                            mods_latched,
+                            mods_locked,
+                            0,
+                            0,
+                            group,
+                        );
                     }
                 }
             }
@@ -232,31 +399,63 @@ impl Dispatch<wl_keyboard::WlKeyboard, ()> for RenderState {
     }
 }
 
-impl WaylandRenderer {
-    pub fn new(app_config: AppConfig) -> Result<Self> {
+impl<'a> WaylandRenderer<'a> {
+    pub fn new(app_config: &'a AppConfig, desktop_outputs: Vec<DesktopOutput>) -> Result<Self> {
         Ok(Self {
             app_config,
+            desktop_outputs,
+            placements: HashMap::new(),
+            contents: HashMap::new(),
         })
     }
 
+    /// Lay out all `hints`, drawing them is done in `wait_for_hint_selection`.
+    ///
+    /// The boxes are placed in the order of `desktop_windows` just like on X11 so that they get
+    /// nudged out of each other's way the same.
     pub fn render_hints(
         &mut self,
-        _desktop_windows: &[DesktopWindow],
+        desktop_windows: &[DesktopWindow],
         hints: &HashMap<String, &DesktopWindow>,
     ) -> Result<()> {
         info!("Rendering {} hints", hints.len());
-        // Rendering will be done in wait_for_hint_selection
+
+        let mut window_hints = vec![];
+        let mut text_extents = vec![];
+        for desktop_window in desktop_windows {
+            let Some(hint) = hints
+                .iter()
+                .find(|(_, w)| std::ptr::eq(**w, desktop_window))
+                .map(|(hint, _)| hint)
+            else {
+                continue;
+            };
+
+            let content = utils::hint_content(self.app_config, hint, desktop_window)
+                .context("Couldn't create extents for text")?;
+            window_hints.push(hint.clone());
+            text_extents.push((desktop_window, content.extents));
+            self.contents.insert(hint.clone(), content);
+        }
+
+        let output_rects: Vec<_> = self
+            .desktop_outputs
+            .iter()
+            .map(|o| (o.pos.0, o.pos.1, o.size.0, o.size.1))
+            .collect();
+        let placements = layout::layout_hints(self.app_config, &text_extents, &output_rects);
+        self.placements = window_hints.into_iter().zip(placements).collect();
         Ok(())
     }
 
-    pub fn wait_for_hint_selection<'a>(
+    pub fn wait_for_hint_selection<'w>(
         &mut self,
-        hints: &HashMap<String, &'a DesktopWindow>,
-    ) -> Result<Option<&'a DesktopWindow>> {
+        hints: &HashMap<String, &'w DesktopWindow>,
+    ) -> Result<Option<(String, &'w DesktopWindow)>> {
         let conn = Connection::connect_to_env().context("Failed to connect to Wayland")?;
 
-        let (globals, mut event_queue) = registry_queue_init::<RenderState>(&conn)
-            .context("Failed to get global registry")?;
+        let (globals, mut event_queue) =
+            registry_queue_init::<RenderState>(&conn).context("Failed to get global registry")?;
 
         let qh = event_queue.handle();
 
@@ -276,12 +475,27 @@ impl WaylandRenderer {
             .bind(&qh, 1..=7, ())
             .context("Failed to bind wl_seat")?;
 
+        // Fractional scaling needs both of these, otherwise we fall back to integer scales
+        let fractional_scaling = match (
+            globals.bind::<wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1, _, _>(
+                &qh,
+                1..=1,
+                (),
+            ),
+            globals.bind::<wp_viewporter::WpViewporter, _, _>(&qh, 1..=1, ()),
+        ) {
+            (Ok(fractional_scale_manager), Ok(viewporter)) => {
+                Some((fractional_scale_manager, viewporter))
+            }
+            _ => None,
+        };
+
         let mut state = RenderState {
             _compositor: compositor.clone(),
             _shm: shm.clone(),
             _layer_shell: layer_shell.clone(),
             _seat: Some(seat.clone()),
-            configured: false,
+            outputs: Vec::new(),
             keyboard_state: Some(KeyboardState {
                 xkb_context: xkbcommon::xkb::Context::new(xkbcommon::xkb::CONTEXT_NO_FLAGS),
                 xkb_state: None,
@@ -290,185 +504,547 @@ impl WaylandRenderer {
             should_exit: false,
         };
 
-        // Get screen dimensions from Hyprland
-        let monitors = hyprland::data::Monitors::get().context("Failed to get monitors")?;
-        let monitor_vec = monitors.to_vec();
-
-        let screen_width = monitor_vec.iter()
-            .map(|m| m.x + m.width as i32)
-            .max()
-            .unwrap_or(1920);
-        let screen_height = monitor_vec.iter()
-            .map(|m| m.y + m.height as i32)
-            .max()
-            .unwrap_or(1080);
-
-        info!("Screen size: {}x{}", screen_width, screen_height);
-
-        // Create full-screen overlay surface
-        let surface = compositor.create_surface(&qh, ());
-        let layer_surface = layer_shell.get_layer_surface(
-            &surface,
-            None,
-            Layer::Overlay,
-            "wmfocus".to_string(),
-            &qh,
-            (),
-        );
+        // Bind every output so that we can put a layer surface on each of them
+        let output_globals: Vec<(u32, u32)> = globals.contents().with_list(|list| {
+            list.iter()
+                .filter(|global| global.interface == "wl_output")
+                .map(|global| (global.name, global.version))
+                .collect()
+        });
+        for (index, (name, version)) in output_globals.into_iter().enumerate() {
+            let wl_output = globals.registry().bind::<wl_output::WlOutput, _, _>(
+                name,
+                version.min(4),
+                &qh,
+                index,
+            );
+            state.outputs.push(OutputState {
+                wl_output,
+                name: None,
+                position: (0, 0),
+                configured_size: None,
+                scale_factor: 1,
+                fractional_scale: None,
+                buffers_busy: [false; BUFFER_COUNT],
+            });
+        }
+        if state.outputs.is_empty() {
+            bail!("No Wayland outputs found");
+        }
 
-        layer_surface.set_anchor(Anchor::Top | Anchor::Bottom | Anchor::Left | Anchor::Right);
-        layer_surface.set_keyboard_interactivity(KeyboardInteractivity::Exclusive);
-        layer_surface.set_exclusive_zone(-1);
+        // Receive output names, positions and scales
+        event_queue.roundtrip(&mut state)?;
 
-        surface.commit();
+        // Create one full-screen overlay surface per output
+        let mut surfaces: Vec<_> = state
+            .outputs
+            .iter()
+            .enumerate()
+            .map(|(index, output)| {
+                let surface = compositor.create_surface(&qh, ());
+                let layer_surface = layer_shell.get_layer_surface(
+                    &surface,
+                    Some(&output.wl_output),
+                    Layer::Overlay,
+                    "wmfocus".to_string(),
+                    &qh,
+                    index,
+                );
+
+                layer_surface
+                    .set_anchor(Anchor::Top | Anchor::Bottom | Anchor::Left | Anchor::Right);
+                layer_surface.set_keyboard_interactivity(KeyboardInteractivity::Exclusive);
+                layer_surface.set_exclusive_zone(-1);
+
+                let (viewport, fractional_scale) = match &fractional_scaling {
+                    Some((fractional_scale_manager, viewporter)) => (
+                        Some(viewporter.get_viewport(&surface, &qh, ())),
+                        Some(fractional_scale_manager.get_fractional_scale(&surface, &qh, index)),
+                    ),
+                    None => (None, None),
+                };
+
+                surface.commit();
+                OutputSurface {
+                    surface,
+                    _layer_surface: layer_surface,
+                    viewport,
+                    _fractional_scale: fractional_scale,
+                    buffer_size: (0, 0),
+                    buffers: Vec::new(),
+                }
+            })
+            .collect();
 
         // Wait for configure
-        while !state.configured {
+        while state.outputs.iter().any(|o| o.configured_size.is_none()) {
             event_queue.blocking_dispatch(&mut state)?;
         }
 
+        // Receive the preferred fractional scales
+        event_queue.roundtrip(&mut state)?;
+
         // Get keyboard
         let _keyboard = seat.get_keyboard(&qh, ());
 
-        // Create buffer with hints rendered
-        let buffer = self.create_hints_buffer(&shm, &qh, screen_width, screen_height, hints)?;
-
-        surface.attach(Some(&buffer), 0, 0);
-        surface.damage_buffer(0, 0, screen_width, screen_height);
-        surface.commit();
-
-        event_queue.roundtrip(&mut state)?;
+        // Create the buffers for each output
+        for (output_index, (output_surface, output)) in
+            surfaces.iter_mut().zip(&state.outputs).enumerate()
+        {
+            let (width, height) = self.output_size(output);
+            let scale = output.scale();
+            info!(
+                "Output {:?} at {:?} with size {}x{} and scale {}",
+                output.name,
+                self.output_origin(output),
+                width,
+                height,
+                scale
+            );
+
+            // Render at physical resolution and let the compositor scale it back down to the
+            // logical size of the surface
+            output_surface.buffer_size = (
+                (f64::from(width) * scale).ceil() as i32,
+                (f64::from(height) * scale).ceil() as i32,
+            );
+            output_surface.buffers = (0..BUFFER_COUNT)
+                .map(|buffer_index| {
+                    create_shm_buffer(
+                        &shm,
+                        &qh,
+                        output_surface.buffer_size,
+                        (output_index, buffer_index),
+                    )
+                })
+                .collect::<Result<_>>()?;
+
+            match &output_surface.viewport {
+                Some(viewport) => viewport.set_destination(width, height),
+                None => output_surface.surface.set_buffer_scale(output.scale_factor),
+            }
+        }
 
         info!("Overlay displayed. Press hint keys or ESC to cancel.");
 
-        // Event loop
-        while !state.should_exit {
+        // Event loop, redrawing whenever the typed keys changed
+        let mut rendered_keys = None;
+        loop {
+            if rendered_keys.as_ref() != Some(&state.pressed_keys)
+                && self.redraw(&mut state, &mut surfaces, hints)?
+            {
+                rendered_keys = Some(state.pressed_keys.clone());
+            }
+
             event_queue.blocking_dispatch(&mut state)?;
+            if state.should_exit {
+                break;
+            }
 
             // Check if pressed_keys matches any hint
             if let Some(window) = hints.get(&state.pressed_keys) {
                 info!("Hint '{}' selected", state.pressed_keys);
-                return Ok(Some(window));
+                return Ok(Some((state.pressed_keys, window)));
+            }
+
+            // Ignore keys that don't lead to any hint
+            if !hints.keys().any(|h| h.starts_with(&state.pressed_keys)) {
+                state.pressed_keys.pop();
             }
         }
 
         Ok(None)
     }
 
-    fn create_hints_buffer(
-        &self,
-        shm: &wl_shm::WlShm,
-        qh: &QueueHandle<RenderState>,
-        width: i32,
-        height: i32,
-        hints: &HashMap<String, &DesktopWindow>,
-    ) -> Result<wl_buffer::WlBuffer> {
-        let stride = width * 4;
-        let size = stride * height;
-
-        let temp_file = tempfile::tempfile().context("Failed to create temp file")?;
-        temp_file.set_len(size as u64).context("Failed to set file size")?;
-
-        let mut cairo_surface = cairo::ImageSurface::create(
-            cairo::Format::ARgb32,
-            width,
-            height,
-        )
-        .context("Failed to create Cairo surface")?;
-
-        {
-            let cairo_context = cairo::Context::new(&cairo_surface)
-                .context("Failed to create Cairo context")?;
+    /// Find the `DesktopOutput` that the window manager reports for `output`.
+    fn desktop_output(&self, output: &OutputState) -> Option<&DesktopOutput> {
+        let name = output.name.as_ref()?;
+        self.desktop_outputs.iter().find(|o| &o.name == name)
+    }
 
-            // Transparent background
-            cairo_context.set_source_rgba(0.0, 0.0, 0.0, 0.0);
-            cairo_context.paint().context("Failed to paint background")?;
+    /// Position of `output` in the global space that `DesktopWindow`s are positioned in.
+    fn output_origin(&self, output: &OutputState) -> (i32, i32) {
+        self.desktop_output(output)
+            .map_or(output.position, |o| o.pos)
+    }
 
-            // Draw hint for each window
-            for (hint, window) in hints {
-                self.draw_hint(&cairo_context, hint, window)?;
-            }
+    /// Size of the buffer for `output`.
+    ///
+    /// This is the size that the compositor configured our layer surface with, unless it left
+    /// that up to us.
+    fn output_size(&self, output: &OutputState) -> (i32, i32) {
+        match (output.configured_size, self.desktop_output(output)) {
+            (Some((width, height)), _) if width > 0 && height > 0 => (width, height),
+            (_, Some(desktop_output)) => desktop_output.size,
+            _ => (1920, 1080),
         }
+    }
 
-        cairo_surface.flush();
-        let cairo_data = cairo_surface.data().context("Failed to get Cairo data")?;
-
-        // Copy to Wayland buffer
-        let mut mmap = unsafe {
-            memmap2::MmapMut::map_mut(&temp_file).context("mmap failed")?
+    /// Draw the hints for the keys typed so far into a free buffer of each output and show it.
+    ///
+    /// Returns false without drawing anything if the compositor still holds on to all buffers of
+    /// some output. It will release one of them eventually so we just try again later.
+    fn redraw(
+        &self,
+        state: &mut RenderState,
+        surfaces: &mut [OutputSurface],
+        hints: &HashMap<String, &DesktopWindow>,
+    ) -> Result<bool> {
+        let free_buffers: Option<Vec<usize>> = state
+            .outputs
+            .iter()
+            .map(|output| output.buffers_busy.iter().position(|busy| !busy))
+            .collect();
+        let Some(free_buffers) = free_buffers else {
+            return Ok(false);
         };
 
-        mmap.copy_from_slice(&cairo_data);
-        drop(mmap);
+        for ((output_surface, output), buffer_index) in surfaces
+            .iter_mut()
+            .zip(&mut state.outputs)
+            .zip(free_buffers)
+        {
+            let (width, height) = output_surface.buffer_size;
+            let mut cairo_surface =
+                cairo::ImageSurface::create(cairo::Format::ARgb32, width, height)
+                    .context("Failed to create Cairo surface")?;
+            self.draw_hints(
+                &cairo_surface,
+                self.output_origin(output),
+                output.scale(),
+                hints,
+                &state.pressed_keys,
+            )?;
+
+            cairo_surface.flush();
+            let cairo_data = cairo_surface.data().context("Failed to get Cairo data")?;
+
+            // Copy to Wayland buffer
+            let buffer = &mut output_surface.buffers[buffer_index];
+            buffer.mmap.copy_from_slice(&cairo_data);
+            output.buffers_busy[buffer_index] = true;
+
+            let surface = &output_surface.surface;
+            surface.attach(Some(&buffer.wl_buffer), 0, 0);
+            surface.damage_buffer(0, 0, width, height);
+            surface.commit();
+        }
+        Ok(true)
+    }
 
-        let pool = shm.create_pool(temp_file.as_fd(), size, qh, ());
-        let buffer = pool.create_buffer(
-            0,
-            width,
-            height,
-            stride,
-            wl_shm::Format::Argb8888,
-            qh,
-            (),
-        );
+    /// Draw all `hints` onto `cairo_surface` for the output at `origin`.
+    ///
+    /// Hints are positioned in global logical coordinates so they are translated into the output's
+    /// local space and scaled up to the physical resolution of `cairo_surface`. Anything that
+    /// doesn't fall onto this output is clipped.
+    ///
+    /// Hints that don't start with `pressed_keys` are dimmed. With `--dim`, the desktop is darkened
+    /// underneath the hints.
+    fn draw_hints(
+        &self,
+        cairo_surface: &cairo::ImageSurface,
+        origin: (i32, i32),
+        scale: f64,
+        hints: &HashMap<String, &DesktopWindow>,
+        pressed_keys: &str,
+    ) -> Result<()> {
+        let cairo_context =
+            cairo::Context::new(cairo_surface).context("Failed to create Cairo context")?;
+
+        // Transparent background
+        cairo_context.set_operator(cairo::Operator::Clear);
+        cairo_context
+            .paint()
+            .context("Failed to paint background")?;
+        cairo_context.set_operator(cairo::Operator::Over);
+
+        // Move into the output's local space
+        cairo_context.scale(scale, scale);
+        cairo_context.translate(-f64::from(origin.0), -f64::from(origin.1));
+
+        if let Some(dim_color) = self.app_config.dim {
+            let windows: Vec<_> = hints
+                .iter()
+                .filter_map(|(hint, window)| {
+                    let content = self.contents.get(hint)?;
+                    Some((*window, &content.style, hint.starts_with(pressed_keys)))
+                })
+                .collect();
+            utils::draw_dim_overlay(&cairo_context, dim_color, &windows)?;
+        }
 
-        pool.destroy();
-        Ok(buffer)
+        // Draw hint for each window, the ones that still match on top
+        let mut hints: Vec<_> = hints.iter().collect();
+        hints.sort_by_key(|(hint, _)| hint.starts_with(pressed_keys));
+        for (hint, window) in hints {
+            if hint.starts_with(pressed_keys) {
+                self.draw_hint(&cairo_context, hint, window, pressed_keys)?;
+            } else {
+                cairo_context.push_group();
+                self.draw_hint(&cairo_context, hint, window, "")?;
+                cairo_context.pop_group_to_source()?;
+                cairo_context.paint_with_alpha(utils::DIMMED_HINT_ALPHA)?;
+            }
+        }
+        Ok(())
     }
 
-    fn draw_hint(&self, ctx: &cairo::Context, hint: &str, window: &DesktopWindow) -> Result<()> {
-        let x = window.pos.0 as f64;
-        let y = window.pos.1 as f64;
-
-        // Set font first to get accurate text extents
-        ctx.select_font_face(
-            &self.app_config.font.font_family,
-            cairo::FontSlant::Normal,
-            cairo::FontWeight::Bold,
+    /// Draw a single hint with the already typed `pressed_keys` highlighted.
+    fn draw_hint(
+        &self,
+        ctx: &cairo::Context,
+        hint: &str,
+        window: &DesktopWindow,
+        pressed_keys: &str,
+    ) -> Result<()> {
+        let (Some(placement), Some(content)) = (self.placements.get(hint), self.contents.get(hint))
+        else {
+            return Ok(());
+        };
+        let (x, y, width, height) = placement.rect;
+        let (x, y, width, height) = (
+            f64::from(x),
+            f64::from(y),
+            f64::from(width),
+            f64::from(height),
         );
-        ctx.set_font_size(self.app_config.font.font_size);
-
-        let text_extents = ctx.text_extents(hint)?;
 
-        // Use margin from config
-        let base_size = self.app_config.font.font_size;
-        let margin = base_size * self.app_config.margin as f64;
+        let style = &content.style;
+        let bg = style.bg_color;
+        ctx.set_source_rgba(bg.0, bg.1, bg.2, bg.3);
 
-        let rect_width = text_extents.width() + margin * 2.0;
-        let rect_height = base_size + margin * 2.0;
+        // Point back to the window if the box had to be moved out of it
+        if let Some((from, to)) = placement.leader_line {
+            ctx.set_line_width(2.0);
+            ctx.move_to(f64::from(from.0), f64::from(from.1));
+            ctx.line_to(f64::from(to.0), f64::from(to.1));
+            ctx.stroke()?;
+        }
 
         // Draw rounded rectangle background
-        let bg = if window.is_focused {
-            self.app_config.bg_color_current
-        } else {
-            self.app_config.bg_color
-        };
-        ctx.set_source_rgba(bg.0, bg.1, bg.2, bg.3);
 
         let radius = 5.0;
         let degrees = std::f64::consts::PI / 180.0;
 
         ctx.new_sub_path();
-        ctx.arc(x + rect_width - radius, y + radius, radius, -90.0 * degrees, 0.0 * degrees);
-        ctx.arc(x + rect_width - radius, y + rect_height - radius, radius, 0.0 * degrees, 90.0 * degrees);
-        ctx.arc(x + radius, y + rect_height - radius, radius, 90.0 * degrees, 180.0 * degrees);
-        ctx.arc(x + radius, y + radius, radius, 180.0 * degrees, 270.0 * degrees);
+        ctx.arc(
+            x + width - radius,
+            y + radius,
+            radius,
+            -90.0 * degrees,
+            0.0 * degrees,
+        );
+        ctx.arc(
+            x + width - radius,
+            y + height - radius,
+            radius,
+            0.0 * degrees,
+            90.0 * degrees,
+        );
+        ctx.arc(
+            x + radius,
+            y + height - radius,
+            radius,
+            90.0 * degrees,
+            180.0 * degrees,
+        );
+        ctx.arc(
+            x + radius,
+            y + radius,
+            radius,
+            180.0 * degrees,
+            270.0 * degrees,
+        );
         ctx.close_path();
-        ctx.fill()?;
-
-        // Hint text
-        let text = if window.is_focused {
-            self.app_config.text_color_current
+        if let Some((border_width, color)) = style.border {
+            ctx.fill_preserve()?;
+            ctx.set_source_rgba(color.0, color.1, color.2, color.3);
+            ctx.set_line_width(border_width);
+            ctx.stroke()?;
         } else {
-            self.app_config.text_color
-        };
-        ctx.set_source_rgba(text.0, text.1, text.2, text.3);
+            ctx.fill()?;
+        }
 
-        let text_x = x + margin;
-        let text_y = y + margin + text_extents.height();
-        ctx.move_to(text_x, text_y);
-        ctx.show_text(hint)?;
+        // Workspace tiles get a miniature of their windows on top of the background
+        if let HintKind::Workspace(miniature) = &window.kind {
+            utils::draw_miniature(ctx, miniature, (x, y, width, height), style.text_color_alt)?;
+        }
+
+        let text = &content.text;
+        let cursor = (x + placement.draw_pos.0, y + placement.draw_pos.1);
+        if let Some(icon) = &content.icon {
+            utils::draw_icon(ctx, icon, cursor)?;
+        }
+
+        // Hint text, with the already typed chars in the alternate color
+        text::draw_text(
+            ctx,
+            text,
+            &self.app_config.font.description,
+            style.font_size,
+            cursor,
+            utils::typed_len(text, pressed_keys),
+            (style.text_color, style.text_color_alt),
+        )?;
 
         Ok(())
     }
 }
+
+/// Create a buffer of `size` physical pixels in shared memory.
+fn create_shm_buffer(
+    shm: &wl_shm::WlShm,
+    qh: &QueueHandle<RenderState>,
+    size: (i32, i32),
+    udata: (usize, usize),
+) -> Result<ShmBuffer> {
+    let (width, height) = size;
+    let stride = width * 4;
+    let size = stride * height;
+
+    let temp_file = tempfile::tempfile().context("Failed to create temp file")?;
+    temp_file
+        .set_len(size as u64)
+        .context("Failed to set file size")?;
+    let mmap = unsafe { memmap2::MmapMut::map_mut(&temp_file).context("mmap failed")? };
+
+    let pool = shm.create_pool(temp_file.as_fd(), size, qh, ());
+    let wl_buffer = pool.create_buffer(
+        0,
+        width,
+        height,
+        stride,
+        wl_shm::Format::Argb8888,
+        qh,
+        udata,
+    );
+
+    pool.destroy();
+    Ok(ShmBuffer { wl_buffer, mmap })
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    /// Alpha of the pixel at (`x`, `y`) of `cairo_surface`.
+    fn alpha_at(cairo_surface: &mut cairo::ImageSurface, x: i32, y: i32) -> u8 {
+        let stride = cairo_surface.stride();
+        let data = cairo_surface.data().unwrap();
+        // ARGB32 is stored in native endianness.
+        let pixel = u32::from_ne_bytes(
+            data[(y * stride + x * 4) as usize..][..4]
+                .try_into()
+                .unwrap(),
+        );
+        (pixel >> 24) as u8
+    }
+
+    fn window(pos: (i32, i32)) -> DesktopWindow {
+        DesktopWindow {
+            pos,
+            size: (100, 50),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_draw_hints_at_physical_resolution() {
+        let app_config = AppConfig::parse_from(["wmfocus", "--font", "Mono:40"]);
+        let mut renderer = WaylandRenderer::new(&app_config, vec![]).unwrap();
+        let windows = [window((-180, 30))];
+        let hints = HashMap::from([("a".to_string(), &windows[0])]);
+        renderer.render_hints(&windows, &hints).unwrap();
+
+        // The output is 200x100 logical pixels and sits left of the origin.
+        for scale in [1.0, 1.5, 2.0] {
+            let size = ((200.0 * scale) as i32, (100.0 * scale) as i32);
+            let mut cairo_surface =
+                cairo::ImageSurface::create(cairo::Format::ARgb32, size.0, size.1).unwrap();
+            renderer
+                .draw_hints(&cairo_surface, (-200, 0), scale, &hints, "")
+                .unwrap();
+            cairo_surface.flush();
+
+            // The hint box starts at the logical output-local position (20, 30).
+            let physical = |x: f64, y: f64| ((x * scale) as i32, (y * scale) as i32);
+            let (x, y) = physical(30.0, 40.0);
+            assert_ne!(alpha_at(&mut cairo_surface, x, y), 0, "scale {scale}");
+            let (x, y) = physical(15.0, 25.0);
+            assert_eq!(alpha_at(&mut cairo_surface, x, y), 0, "scale {scale}");
+
+            // Without scaling the box would cover this pixel too.
+            if scale > 1.0 {
+                let (x, y) = (25, 35);
+                assert_eq!(alpha_at(&mut cairo_surface, x, y), 0, "scale {scale}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_draw_hints_dims_hints_not_matching_typed_keys() {
+        let app_config = AppConfig::parse_from(["wmfocus", "--font", "Mono:40"]);
+        let mut renderer = WaylandRenderer::new(&app_config, vec![]).unwrap();
+        let windows = [window((20, 30)), window((20, 130))];
+        let hints = HashMap::from([
+            ("sa".to_string(), &windows[0]),
+            ("as".to_string(), &windows[1]),
+        ]);
+        renderer.render_hints(&windows, &hints).unwrap();
+        let mut cairo_surface =
+            cairo::ImageSurface::create(cairo::Format::ARgb32, 200, 200).unwrap();
+
+        renderer
+            .draw_hints(&cairo_surface, (0, 0), 1.0, &hints, "")
+            .unwrap();
+        cairo_surface.flush();
+        let first_alpha = alpha_at(&mut cairo_surface, 22, 32);
+        assert_eq!(first_alpha, alpha_at(&mut cairo_surface, 22, 132));
+
+        renderer
+            .draw_hints(&cairo_surface, (0, 0), 1.0, &hints, "s")
+            .unwrap();
+        cairo_surface.flush();
+        assert_eq!(alpha_at(&mut cairo_surface, 22, 32), first_alpha);
+        let dimmed_alpha = alpha_at(&mut cairo_surface, 22, 132);
+        assert_ne!(dimmed_alpha, 0);
+        assert!(dimmed_alpha < first_alpha);
+    }
+
+    #[test]
+    fn test_draw_hints_dims_desktop_around_matching_windows() {
+        let app_config = AppConfig::parse_from([
+            "wmfocus",
+            "--font",
+            "Mono:20",
+            "--dim",
+            "rgba(0, 0, 0, 0.5)",
+        ]);
+        let mut renderer = WaylandRenderer::new(&app_config, vec![]).unwrap();
+        let windows = [window((0, 0)), window((100, 100))];
+        let hints = HashMap::from([
+            ("a".to_string(), &windows[0]),
+            ("s".to_string(), &windows[1]),
+        ]);
+        renderer.render_hints(&windows, &hints).unwrap();
+        let mut cairo_surface =
+            cairo::ImageSurface::create(cairo::Format::ARgb32, 200, 200).unwrap();
+
+        renderer
+            .draw_hints(&cairo_surface, (0, 0), 1.0, &hints, "a")
+            .unwrap();
+        cairo_surface.flush();
+        let dimmed = alpha_at(&mut cairo_surface, 150, 50);
+        assert!((127..=128).contains(&dimmed));
+        // The matching window is cut out, the other one stays dark.
+        assert_eq!(alpha_at(&mut cairo_surface, 80, 40), 0);
+        assert_eq!(alpha_at(&mut cairo_surface, 180, 140), dimmed);
+        // Both are outlined, the one that doesn't match anymore faintly.
+        let outline = alpha_at(&mut cairo_surface, 80, 1);
+        let faded_outline = alpha_at(&mut cairo_surface, 180, 101);
+        assert_eq!(outline, 255);
+        assert!(dimmed < faded_outline && faded_outline < outline);
+    }
+}
@@ -0,0 +1,120 @@
+use std::env;
+
+use anyhow::{bail, Result};
+use log::debug;
+
+use crate::args::Backend;
+use crate::{DesktopOutput, DesktopWindow, DesktopWorkspace};
+
+/// Name of the hidden workspace that i3 and sway keep scratchpad windows on.
+pub const SCRATCHPAD_WORKSPACE: &str = "__i3_scratch";
+
+/// Everything wmfocus needs from a window manager.
+pub trait WindowManager {
+    /// Return a list of all windows that should get a hint.
+    ///
+    /// Only windows on visible workspaces are returned unless `all_workspaces` is set, in which
+    /// case windows on hidden workspaces and in the scratchpad are returned as well.
+    fn get_windows(&self, all_workspaces: bool) -> Result<Vec<DesktopWindow>>;
+
+    /// Focus a specific `window`, switching to its workspace if needed.
+    fn focus_window(&self, window: &DesktopWindow) -> Result<()>;
+
+    /// Swap `active_window` with `window`.
+    fn swap_windows(&self, active_window: &DesktopWindow, window: &DesktopWindow) -> Result<()>;
+
+    /// Return a list of all active outputs.
+    fn get_outputs(&self) -> Result<Vec<DesktopOutput>>;
+
+    /// Return a list of all workspaces.
+    fn get_workspaces(&self) -> Result<Vec<DesktopWorkspace>>;
+
+    /// Switch to `workspace`.
+    fn focus_workspace(&self, workspace: &DesktopWorkspace) -> Result<()>;
+
+    /// Focus `output`.
+    fn focus_output(&self, output: &DesktopOutput) -> Result<()>;
+
+    /// Move `window` to `output`.
+    fn move_window_to_output(&self, window: &DesktopWindow, output: &DesktopOutput) -> Result<()>;
+
+    /// Describe `window` for `--print-only`.
+    ///
+    /// This is the id that the tools of the window manager take, followed by the `details` from
+    /// `window_details` if requested.
+    fn describe_window(&self, window: &DesktopWindow, details: bool) -> Result<String>;
+
+    /// Call `on_event` for every change to a window until the window manager goes away.
+    fn watch(&self, on_event: &mut dyn FnMut(WindowEvent) -> Result<()>) -> Result<()>;
+}
+
+/// A change to a window that `--track` records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowEvent {
+    /// The window with this id got focus.
+    Focused(i64),
+    /// The window with this id became urgent or stopped being urgent.
+    Urgent(i64, bool),
+    /// The window with this id was closed.
+    Closed(i64),
+}
+
+/// `id` followed by the tab-separated class, workspace, `pid` and title of `window` for
+/// `--print-details`. Unknown values are left empty.
+pub fn window_details(id: &str, window: &DesktopWindow, pid: Option<i32>) -> String {
+    format!(
+        "{id}\t{}\t{}\t{}\t{}",
+        window.class.as_deref().unwrap_or_default(),
+        window.workspace.as_deref().unwrap_or_default(),
+        pid.map(|p| p.to_string()).unwrap_or_default(),
+        window.title.as_deref().unwrap_or_default(),
+    )
+}
+
+/// Quote `arg` so that it can be used as a single argument of an i3 or sway command.
+#[cfg(any(feature = "i3", feature = "sway"))]
+pub fn quote_command_arg(arg: &str) -> String {
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Figure out which window manager we're running under by looking at the environment.
+///
+/// Window manager specific sockets are checked first since they are the most reliable hint. We
+/// only fall back to the generic display variables if none of them are set.
+pub fn detect_backend() -> Option<Backend> {
+    let is_set = |var: &str| env::var_os(var).is_some_and(|v| !v.is_empty());
+
+    let backend = if is_set("HYPRLAND_INSTANCE_SIGNATURE") {
+        Some(Backend::Hyprland)
+    } else if is_set("SWAYSOCK") {
+        Some(Backend::Sway)
+    } else if is_set("I3SOCK") {
+        Some(Backend::I3)
+    } else if is_set("WAYLAND_DISPLAY") {
+        Some(Backend::Hyprland)
+    } else if is_set("DISPLAY") {
+        Some(Backend::I3)
+    } else {
+        None
+    };
+    debug!("Detected backend: {:?}", backend);
+    backend
+}
+
+/// Return the `WindowManager` implementation for `backend`.
+pub fn connect(backend: Backend) -> Result<Box<dyn WindowManager>> {
+    match backend {
+        #[cfg(feature = "i3")]
+        Backend::I3 => Ok(Box::new(crate::wm_i3::I3)),
+        #[cfg(feature = "hyprland")]
+        Backend::Hyprland => Ok(Box::new(crate::wm_hyprland::Hyprland)),
+        #[cfg(feature = "sway")]
+        Backend::Sway => Ok(Box::new(crate::wm_sway::Sway)),
+        #[allow(unreachable_patterns)]
+        _ => bail!(
+            "wmfocus was built without support for {:?}, rebuild it with --features {}",
+            backend,
+            backend.feature_name()
+        ),
+    }
+}
@@ -1,137 +1,364 @@
+use std::env;
+use std::io::{BufRead, BufReader};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+
 use anyhow::{Context, Result};
-use hyprland::data::{Client, Clients, Monitors};
-use hyprland::dispatch::{Dispatch as HyprDispatch, DispatchType, WindowIdentifier};
+use hyprland::data::{
+    Client, Clients, FullscreenMode, Monitor, Monitors, Transforms, Workspace, Workspaces,
+};
+use hyprland::dispatch::{
+    Dispatch as HyprDispatch, DispatchType, MonitorIdentifier, Position, WindowIdentifier,
+    WindowMove, WorkspaceIdentifierWithSpecial,
+};
 use hyprland::prelude::*;
-use log::{debug, info, warn};
-
-use crate::DesktopWindow;
+use log::{debug, info};
 
-/// Return a list of all visible windows on active workspaces.
-pub fn get_windows() -> Result<Vec<DesktopWindow>> {
-    // Get all clients
-    let clients = Clients::get().context("Failed to get clients from Hyprland")?;
-    let client_vec = clients.to_vec();
+use crate::wm::{window_details, WindowEvent, WindowManager};
+use crate::{DesktopOutput, DesktopWindow, DesktopWorkspace, HintKind};
 
-    // Get monitors to determine visible workspaces
-    let monitors = Monitors::get().context("Failed to get monitors from Hyprland")?;
-    let monitor_vec = monitors.to_vec();
+/// Hyprland rendered through a Wayland layer surface.
+pub struct Hyprland;
 
-    // Collect active workspace IDs from all monitors
-    let visible_workspace_ids: Vec<i32> = monitor_vec
+/// Find the `Client` belonging to `window` by its address.
+///
+/// This fails if the window was closed since we listed it.
+fn find_client<'a>(clients: &'a [Client], window: &DesktopWindow) -> Result<&'a Client> {
+    let address = window
+        .address
+        .as_deref()
+        .context("Window has no Hyprland address")?;
+    clients
         .iter()
-        .map(|m| m.active_workspace.id)
-        .collect();
+        .find(|c| c.address.to_string() == address)
+        .with_context(|| format!("Window {address} doesn't exist anymore"))
+}
 
-    debug!("Visible workspace IDs: {:?}", visible_workspace_ids);
+/// Numeric id for the window with `address`.
+///
+/// The address is a pointer so it also makes for a unique numeric id.
+fn address_id(address: &str) -> Option<i64> {
+    i64::from_str_radix(address.trim_start_matches("0x"), 16).ok()
+}
 
-    // Filter clients to only those on visible workspaces
-    let visible_clients: Vec<&Client> = client_vec
-        .iter()
-        .filter(|c| visible_workspace_ids.contains(&c.workspace.id))
-        .collect();
-
-    debug!("Found {} visible windows", visible_clients.len());
-
-    // Get the currently focused client
-    let active_address = hyprland::data::Client::get_active()
-        .ok()
-        .flatten()
-        .map(|c| c.address);
-
-    // Convert to DesktopWindow
-    let mut windows = Vec::new();
-    for client in visible_clients {
-        // Use the address as a unique ID (convert the string representation to a hash)
-        let id = {
-            use std::collections::hash_map::DefaultHasher;
-            use std::hash::{Hash, Hasher};
-            let mut hasher = DefaultHasher::new();
-            client.address.to_string().hash(&mut hasher);
-            hasher.finish() as i64
+/// Path of the socket that Hyprland sends its events to.
+fn event_socket_path() -> Result<PathBuf> {
+    let runtime_dir = env::var_os("XDG_RUNTIME_DIR").context("XDG_RUNTIME_DIR is not set")?;
+    let signature = env::var("HYPRLAND_INSTANCE_SIGNATURE")
+        .context("HYPRLAND_INSTANCE_SIGNATURE is not set")?;
+    Ok(PathBuf::from(runtime_dir)
+        .join("hypr")
+        .join(signature)
+        .join(".socket2.sock"))
+}
+
+/// Turn a line from Hyprland's event socket into the change that `--track` records, if any.
+fn window_event(line: &str) -> Option<WindowEvent> {
+    let (name, data) = line.split_once(">>")?;
+    match name {
+        "activewindowv2" => address_id(data).map(WindowEvent::Focused),
+        // Hyprland has no event for the end of urgency, focusing the window ends it.
+        "urgent" => address_id(data).map(|id| WindowEvent::Urgent(id, true)),
+        "closewindow" => address_id(data).map(WindowEvent::Closed),
+        _ => None,
+    }
+}
+
+/// Logical size of `monitor`, which is the space that clients are positioned in.
+///
+/// Hyprland reports the mode of the monitor in physical pixels, before scaling and rotation.
+fn monitor_size(monitor: &Monitor) -> (i32, i32) {
+    let width = (f32::from(monitor.width) / monitor.scale).round() as i32;
+    let height = (f32::from(monitor.height) / monitor.scale).round() as i32;
+    match monitor.transform {
+        Transforms::Normal90
+        | Transforms::Normal270
+        | Transforms::Flipped90
+        | Transforms::Flipped270 => (height, width),
+        _ => (width, height),
+    }
+}
+
+impl WindowManager for Hyprland {
+    /// Return a list of all windows on visible or, with `all_workspaces`, all workspaces.
+    fn get_windows(&self, all_workspaces: bool) -> Result<Vec<DesktopWindow>> {
+        // Get all clients
+        let clients = Clients::get().context("Failed to get clients from Hyprland")?;
+        let client_vec = clients.to_vec();
+
+        // Get monitors to determine visible workspaces
+        let monitors = Monitors::get().context("Failed to get monitors from Hyprland")?;
+        let monitor_vec = monitors.to_vec();
+
+        // Collect active workspace IDs from all monitors, including opened special workspaces
+        let visible_workspace_ids: Vec<i32> = monitor_vec
+            .iter()
+            .flat_map(|m| [m.active_workspace.id, m.special_workspace.id])
+            .filter(|&id| id != 0)
+            .collect();
+
+        debug!("Visible workspace IDs: {:?}", visible_workspace_ids);
+
+        // Filter clients to only those on visible workspaces unless we want all of them
+        let shown_clients: Vec<&Client> = client_vec
+            .iter()
+            .filter(|c| c.mapped)
+            .filter(|c| all_workspaces || visible_workspace_ids.contains(&c.workspace.id))
+            .collect();
+
+        debug!("Found {} windows", shown_clients.len());
+
+        // Get the currently focused client
+        let active_address = hyprland::data::Client::get_active()
+            .ok()
+            .flatten()
+            .map(|c| c.address);
+
+        // Convert to DesktopWindow
+        let mut windows = Vec::new();
+        for client in shown_clients {
+            let address = client.address.to_string();
+            let id = address_id(&address).unwrap_or_default();
+
+            let window = DesktopWindow {
+                id,
+                x_window_id: None, // Wayland doesn't use X11 window IDs
+                address: Some(address),
+                pos: (client.at.0 as i32, client.at.1 as i32),
+                size: (client.size.0 as i32, client.size.1 as i32),
+                is_focused: active_address.as_ref() == Some(&client.address),
+                title: Some(client.title.clone()),
+                class: Some(client.class.clone()),
+                workspace: Some(client.workspace.name.clone()),
+                output: monitor_vec
+                    .iter()
+                    .find(|m| Some(m.id) == client.monitor)
+                    .map(|m| m.name.clone()),
+                is_floating: client.floating,
+                is_fullscreen: matches!(
+                    client.fullscreen,
+                    FullscreenMode::Fullscreen | FullscreenMode::MaximizedFullscreen
+                ),
+                // Hyprland only announces urgency through events, which `wmfocus --track`
+                // records.
+                is_urgent: false,
+                urgency_history: None,
+                is_visible: visible_workspace_ids.contains(&client.workspace.id),
+                // Hyprland counts up from 0 for the focused window and uses -1 for windows that
+                // were never focused.
+                focus_history: usize::try_from(client.focus_history_id).ok(),
+                kind: HintKind::Window,
+            };
+            debug!("Found window: {:?}", window);
+            windows.push(window);
+        }
+
+        Ok(windows)
+    }
+
+    /// Focus a specific window by its address, Hyprland switches to its workspace on its own.
+    fn focus_window(&self, window: &DesktopWindow) -> Result<()> {
+        let clients = Clients::get().context("Failed to get clients")?.to_vec();
+        let target_client = find_client(&clients, window)?;
+
+        info!("Focusing window {}", target_client.address);
+
+        HyprDispatch::call(DispatchType::FocusWindow(WindowIdentifier::Address(
+            target_client.address.clone(),
+        )))
+        .context("Failed to focus window")?;
+
+        Ok(())
+    }
+
+    /// Swap two windows.
+    ///
+    /// Tiled windows are swapped by the layout itself, which also works across workspaces and
+    /// monitors. Floating windows trade workspaces, positions and sizes. For a floating and a
+    /// tiled window, the floating one is tiled next to the other one first so that the layout can
+    /// hand it the tiled slot, and the other one then floats where the first one was.
+    fn swap_windows(&self, active_window: &DesktopWindow, window: &DesktopWindow) -> Result<()> {
+        let clients = Clients::get().context("Failed to get clients")?.to_vec();
+        let active_client = find_client(&clients, active_window)?;
+        let target_client = find_client(&clients, window)?;
+
+        info!(
+            "Swapping windows {} <-> {}",
+            active_client.address, target_client.address
+        );
+
+        let id = |client: &Client| WindowIdentifier::Address(client.address.clone());
+        let move_to_workspace = |client: &Client, workspace: &Client| {
+            DispatchType::MoveToWorkspaceSilent(
+                WorkspaceIdentifierWithSpecial::Id(workspace.workspace.id),
+                Some(id(client)),
+            )
         };
+        let place = |client: &Client, geometry: &Client| {
+            [
+                DispatchType::MoveWindowPixel(
+                    Position::Exact(geometry.at.0, geometry.at.1),
+                    id(client),
+                ),
+                DispatchType::ResizeWindowPixel(
+                    Position::Exact(geometry.size.0, geometry.size.1),
+                    id(client),
+                ),
+            ]
+        };
+        let same_workspace = active_client.workspace.id == target_client.workspace.id;
+        let (floating, tiled) = match (active_client.floating, target_client.floating) {
+            (true, false) => (active_client, target_client),
+            _ => (target_client, active_client),
+        };
+        let target_address = format!("address:{}", target_client.address);
+        let tiled_address = format!("address:{}", tiled.address);
+
+        let mut dispatches = vec![];
+        match (active_client.floating, target_client.floating) {
+            (false, false) => {
+                // swapwindow always operates on the active window.
+                dispatches.push(DispatchType::FocusWindow(id(active_client)));
+                dispatches.push(DispatchType::Custom("swapwindow", &target_address));
+            }
+            (true, true) => {
+                if !same_workspace {
+                    dispatches.push(move_to_workspace(active_client, target_client));
+                    dispatches.push(move_to_workspace(target_client, active_client));
+                }
+                dispatches.extend(place(active_client, target_client));
+                dispatches.extend(place(target_client, active_client));
+            }
+            _ => {
+                if !same_workspace {
+                    dispatches.push(move_to_workspace(floating, tiled));
+                }
+                dispatches.push(DispatchType::ToggleFloating(Some(id(floating))));
+                dispatches.push(DispatchType::FocusWindow(id(floating)));
+                dispatches.push(DispatchType::Custom("swapwindow", &tiled_address));
+                if !same_workspace {
+                    dispatches.push(move_to_workspace(tiled, floating));
+                }
+                dispatches.push(DispatchType::ToggleFloating(Some(id(tiled))));
+                dispatches.extend(place(tiled, floating));
+            }
+        }
+
+        for dispatch in dispatches {
+            debug!("Dispatching {:?}", dispatch);
+            HyprDispatch::call(dispatch).context("Failed to swap windows")?;
+        }
+
+        Ok(())
+    }
+
+    /// Describe a window by its address, optionally followed by class, workspace, PID and title.
+    fn describe_window(&self, window: &DesktopWindow, details: bool) -> Result<String> {
+        let address = window
+            .address
+            .clone()
+            .context("Window has no Hyprland address")?;
+        if !details {
+            return Ok(address);
+        }
+
+        let clients = Clients::get().context("Failed to get clients")?.to_vec();
+        let client = find_client(&clients, window)?;
+        Ok(window_details(&address, window, Some(client.pid)))
+    }
 
-        let window = DesktopWindow {
+    /// Return a list of all regular workspaces.
+    ///
+    /// Special workspaces are left out since they can only be toggled on top of another one.
+    fn get_workspaces(&self) -> Result<Vec<DesktopWorkspace>> {
+        let workspaces = Workspaces::get().context("Failed to get workspaces from Hyprland")?;
+        let monitors = Monitors::get()
+            .context("Failed to get monitors from Hyprland")?
+            .to_vec();
+        let active_workspace_id = Workspace::get_active().ok().map(|w| w.id);
+
+        let desktop_workspaces = workspaces
+            .into_iter()
+            .filter(|w| w.id > 0)
+            .filter_map(|w| {
+                let monitor = monitors.iter().find(|m| m.name == w.monitor)?;
+                Some(DesktopWorkspace {
+                    id: i64::from(w.id),
+                    output: Some(w.monitor.clone()),
+                    pos: (monitor.x, monitor.y),
+                    size: monitor_size(monitor),
+                    is_focused: active_workspace_id == Some(w.id),
+                    is_visible: monitors.iter().any(|m| m.active_workspace.id == w.id),
+                    name: w.name,
+                })
+            })
+            .collect();
+        Ok(desktop_workspaces)
+    }
+
+    /// Switch to `workspace` by its id.
+    fn focus_workspace(&self, workspace: &DesktopWorkspace) -> Result<()> {
+        let id = i32::try_from(workspace.id).context("Invalid workspace id")?;
+        HyprDispatch::call(DispatchType::Workspace(WorkspaceIdentifierWithSpecial::Id(
             id,
-            x_window_id: None, // Wayland doesn't use X11 window IDs
-            pos: (client.at.0 as i32, client.at.1 as i32),
-            size: (client.size.0 as i32, client.size.1 as i32),
-            is_focused: active_address.as_ref() == Some(&client.address),
-        };
-        debug!("Found window: {:?}", window);
-        windows.push(window);
+        )))
+        .context("Failed to switch workspace")?;
+        Ok(())
     }
 
-    Ok(windows)
-}
+    /// Focus `output` by its monitor name.
+    fn focus_output(&self, output: &DesktopOutput) -> Result<()> {
+        HyprDispatch::call(DispatchType::FocusMonitor(MonitorIdentifier::Name(
+            &output.name,
+        )))
+        .context("Failed to focus monitor")?;
+        Ok(())
+    }
 
-/// Focus a specific window by its ID.
-pub fn focus_window(window: &DesktopWindow) -> Result<()> {
-    // We need to find the client by position since we can't reconstruct the address from ID
-    let clients = Clients::get().context("Failed to get clients")?;
-    let client_vec = clients.to_vec();
+    /// Move `window` to `output`.
+    fn move_window_to_output(&self, window: &DesktopWindow, output: &DesktopOutput) -> Result<()> {
+        let clients = Clients::get().context("Failed to get clients")?.to_vec();
+        let client = find_client(&clients, window)?;
 
-    // Find the client that matches this window's position and size
-    let target_client = client_vec
-        .iter()
-        .find(|c| {
-            c.at.0 as i32 == window.pos.0
-                && c.at.1 as i32 == window.pos.1
-                && c.size.0 as i32 == window.size.0
-                && c.size.1 as i32 == window.size.1
-        })
-        .context("Could not find matching client")?;
-
-    info!("Focusing window at ({}, {})", window.pos.0, window.pos.1);
-
-    HyprDispatch::call(DispatchType::FocusWindow(WindowIdentifier::Address(
-        target_client.address.clone(),
-    )))
-    .context("Failed to focus window")?;
-
-    Ok(())
-}
+        // movewindow always operates on the active window.
+        let dispatches = [
+            DispatchType::FocusWindow(WindowIdentifier::Address(client.address.clone())),
+            DispatchType::MoveWindow(WindowMove::Monitor(MonitorIdentifier::Name(&output.name))),
+        ];
+        for dispatch in dispatches {
+            debug!("Dispatching {:?}", dispatch);
+            HyprDispatch::call(dispatch).context("Failed to move window")?;
+        }
+        Ok(())
+    }
 
-/// Swap two windows.
-#[allow(dead_code)]
-pub fn swap_windows(active_window: &DesktopWindow, window: &DesktopWindow) -> Result<()> {
-    // We need to find the clients by position
-    let clients = Clients::get().context("Failed to get clients")?;
-    let client_vec = clients.to_vec();
+    /// Return a list of all enabled monitors.
+    fn get_outputs(&self) -> Result<Vec<DesktopOutput>> {
+        let monitors = Monitors::get().context("Failed to get monitors from Hyprland")?;
+        let outputs = monitors
+            .into_iter()
+            .filter(|m| !m.disabled)
+            .map(|m| DesktopOutput {
+                size: monitor_size(&m),
+                pos: (m.x, m.y),
+                name: m.name,
+            })
+            .collect();
+        Ok(outputs)
+    }
 
-    let _active_client = client_vec
-        .iter()
-        .find(|c| {
-            c.at.0 as i32 == active_window.pos.0
-                && c.at.1 as i32 == active_window.pos.1
-                && c.size.0 as i32 == active_window.size.0
-                && c.size.1 as i32 == active_window.size.1
-        })
-        .context("Could not find active client")?;
-
-    let target_client = client_vec
-        .iter()
-        .find(|c| {
-            c.at.0 as i32 == window.pos.0
-                && c.at.1 as i32 == window.pos.1
-                && c.size.0 as i32 == window.size.0
-                && c.size.1 as i32 == window.size.1
-        })
-        .context("Could not find target client")?;
-
-    info!(
-        "Swapping windows at ({}, {}) <-> ({}, {})",
-        active_window.pos.0, active_window.pos.1, window.pos.0, window.pos.1
-    );
-
-    // TODO: Implement proper window swapping for Hyprland
-    // Hyprland's SwapWindow uses Direction, not WindowIdentifier
-    // For now, just focus the target window
-    HyprDispatch::call(DispatchType::FocusWindow(WindowIdentifier::Address(
-        target_client.address.clone(),
-    )))
-    .context("Failed to focus target window")?;
-
-    warn!("Window swapping not fully implemented for Hyprland yet");
-
-    Ok(())
+    /// Report focus changes, urgent windows and closed windows from Hyprland's event socket.
+    fn watch(&self, on_event: &mut dyn FnMut(WindowEvent) -> Result<()>) -> Result<()> {
+        let path = event_socket_path()?;
+        let stream = UnixStream::connect(&path).with_context(|| {
+            format!("Couldn't connect to Hyprland events at {}", path.display())
+        })?;
+        for line in BufReader::new(stream).lines() {
+            let line = line.context("Couldn't read Hyprland event")?;
+            if let Some(event) = window_event(&line) {
+                on_event(event)?;
+            }
+        }
+        Ok(())
+    }
 }
@@ -1,9 +1,19 @@
 use anyhow::{Context, Result};
-use i3ipc::reply::{Node, NodeLayout, NodeType, Workspace};
-use i3ipc::I3Connection;
+use i3ipc::event::inner::WindowChange;
+use i3ipc::event::Event;
+use i3ipc::reply::{Node, NodeLayout, NodeType, WindowProperty};
+use i3ipc::{I3Connection, I3EventListener, Subscription};
 use log::{debug, info};
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt};
 
-use crate::DesktopWindow;
+use crate::wm::{
+    quote_command_arg, window_details, WindowEvent, WindowManager, SCRATCHPAD_WORKSPACE,
+};
+use crate::wm_tree::{self, TreeNode, TreeWindow};
+use crate::{DesktopOutput, DesktopWindow, DesktopWorkspace, HintKind};
+
+/// i3 (and anything speaking its IPC protocol) rendered through X11.
+pub struct I3;
 
 /// Find first `Node` that fulfills a given criterion.
 fn find_first_node_with_attr<F>(start_node: &Node, predicate: F) -> Option<&Node>
@@ -24,114 +34,284 @@ where
     None
 }
 
-/// Find parent of `child`.
-fn find_parent_of<'a>(start_node: &'a Node, child: &'a Node) -> Option<&'a Node> {
-    let mut nodes_to_explore: Vec<&Node> = start_node.nodes.iter().collect();
-    while !nodes_to_explore.is_empty() {
-        let mut next_vec = vec![];
-        for node in &nodes_to_explore {
-            if node.nodes.iter().any(|x| child.id == x.id) {
-                return Some(node);
+impl TreeNode for Node {
+    fn id(&self) -> i64 {
+        self.id
+    }
+
+    fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    fn floating_nodes(&self) -> &[Node] {
+        &self.floating_nodes
+    }
+
+    fn focus(&self) -> &[i64] {
+        &self.focus
+    }
+
+    fn is_window(&self) -> bool {
+        self.window.is_some()
+    }
+}
+
+/// Return a list of all `DesktopWindow`s below `workspace_node`.
+///
+/// `output` is the name of the output the workspace is on and `is_visible` tells whether the
+/// workspace is currently shown on it.
+fn crawl_windows(
+    root_node: &Node,
+    workspace_node: &Node,
+    output: Option<&str>,
+    is_visible: bool,
+) -> Vec<DesktopWindow> {
+    let mut windows = vec![];
+    for TreeWindow {
+        node,
+        parent,
+        is_floating,
+    } in wm_tree::crawl_windows(root_node, workspace_node)
+    {
+        let (pos_x, size_x) = if let Some(parent) = parent {
+            if parent.layout == NodeLayout::Tabbed {
+                (node.rect.0 + node.deco_rect.0, node.deco_rect.2)
+            } else {
+                (node.rect.0, node.rect.2)
             }
-            next_vec.extend(node.nodes.iter());
-        }
-        nodes_to_explore = next_vec;
+        } else {
+            (node.rect.0, node.rect.2)
+        };
+
+        let pos_y = if let Some(parent) = parent {
+            if parent.layout == NodeLayout::Stacked {
+                parent.rect.1 + node.deco_rect.1
+            } else {
+                node.rect.1 + node.deco_rect.3
+            }
+        } else {
+            node.rect.1 + node.deco_rect.3
+        };
+
+        let window = DesktopWindow {
+            id: node.id,
+            x_window_id: node.window,
+            address: None,
+            pos: (pos_x, pos_y),
+            size: (size_x, (node.rect.3 + node.deco_rect.3)),
+            is_focused: node.focused,
+            title: node.name.clone(),
+            class: node
+                .window_properties
+                .as_ref()
+                .and_then(|p| p.get(&WindowProperty::Class).cloned()),
+            workspace: workspace_node.name.clone(),
+            output: output.map(str::to_string),
+            is_floating,
+            // i3ipc doesn't expose the fullscreen mode of a node.
+            is_fullscreen: false,
+            is_urgent: node.urgent,
+            urgency_history: None,
+            is_visible,
+            focus_history: None,
+            kind: HintKind::Window,
+        };
+        debug!("Found {:?}", window);
+        windows.push(window);
     }
-    None
+    windows
 }
 
-/// Return a list of all `DesktopWindow`s for the given `Workspace`.
-fn crawl_windows(root_node: &Node, workspace: &Workspace) -> Result<Vec<DesktopWindow>> {
-    let workspace_node = find_first_node_with_attr(root_node, |x| {
-        x.name == Some(workspace.name.clone()) && x.nodetype == NodeType::Workspace
+/// Find the workspace `Node` called `name`.
+fn find_workspace_node<'a>(root_node: &'a Node, name: &str) -> Option<&'a Node> {
+    find_first_node_with_attr(root_node, |x| {
+        x.name.as_deref() == Some(name) && x.nodetype == NodeType::Workspace
     })
-    .context("Couldn't find the Workspace node")?;
+}
 
-    let mut nodes_to_explore: Vec<&Node> = workspace_node.nodes.iter().collect();
-    nodes_to_explore.extend(workspace_node.floating_nodes.iter());
-    let mut windows = vec![];
-    while !nodes_to_explore.is_empty() {
-        let mut next_vec = vec![];
-        for node in &nodes_to_explore {
-            next_vec.extend(node.nodes.iter());
-            next_vec.extend(node.floating_nodes.iter());
-            if node.window.is_some() {
-                let root_node = find_parent_of(root_node, node);
-
-                let (pos_x, size_x) = if let Some(root_node) = root_node {
-                    if root_node.layout == NodeLayout::Tabbed {
-                        (node.rect.0 + node.deco_rect.0, node.deco_rect.2)
-                    } else {
-                        (node.rect.0, node.rect.2)
-                    }
-                } else {
-                    (node.rect.0, node.rect.2)
-                };
-
-                let pos_y = if let Some(root_node) = root_node {
-                    if root_node.layout == NodeLayout::Stacked {
-                        root_node.rect.1 + node.deco_rect.1
-                    } else {
-                        node.rect.1 + node.deco_rect.3
-                    }
-                } else {
-                    node.rect.1 + node.deco_rect.3
-                };
-
-                let window = DesktopWindow {
-                    id: node.id,
-                    x_window_id: node.window,
-                    pos: (pos_x, pos_y),
-                    size: (size_x, (node.rect.3 + node.deco_rect.3)),
-                    is_focused: node.focused,
-                };
-                debug!("Found {:?}", window);
-                windows.push(window);
+/// PID of the client owning the X11 window `x_window_id`, which i3 doesn't report.
+fn window_pid(x_window_id: i32) -> Result<Option<i32>> {
+    let (conn, _) = x11rb::connect(None).context("No Xorg connection")?;
+    let atom = conn.intern_atom(false, b"_NET_WM_PID")?.reply()?.atom;
+    let reply = conn
+        .get_property(false, x_window_id as u32, atom, AtomEnum::CARDINAL, 0, 1)?
+        .reply()?;
+    Ok(reply.value32().and_then(|mut v| v.next()).map(|p| p as i32))
+}
+
+impl WindowManager for I3 {
+    /// Return a list of all windows on visible or, with `all_workspaces`, all workspaces.
+    fn get_windows(&self, all_workspaces: bool) -> Result<Vec<DesktopWindow>> {
+        // Establish a connection to i3 over a unix socket
+        let mut connection = I3Connection::connect().context("Couldn't acquire i3 connection")?;
+        let workspaces = connection
+            .get_workspaces()
+            .context("Problem communicating with i3")?
+            .workspaces;
+        let shown_workspaces = workspaces.iter().filter(|w| all_workspaces || w.visible);
+        let root_node = connection.get_tree()?;
+        let mut windows = vec![];
+        for workspace in shown_workspaces {
+            let workspace_node = find_workspace_node(&root_node, &workspace.name)
+                .context("Couldn't find the Workspace node")?;
+            windows.extend(crawl_windows(
+                &root_node,
+                workspace_node,
+                Some(&workspace.output),
+                workspace.visible,
+            ));
+        }
+
+        // The scratchpad is a workspace of its own that i3 doesn't report.
+        if all_workspaces {
+            if let Some(scratchpad_node) = find_workspace_node(&root_node, SCRATCHPAD_WORKSPACE) {
+                windows.extend(crawl_windows(&root_node, scratchpad_node, None, false));
             }
         }
-        nodes_to_explore = next_vec;
+
+        let focus_history = wm_tree::focus_history(&root_node);
+        for window in &mut windows {
+            window.focus_history = focus_history.iter().position(|id| *id == window.id);
+        }
+        Ok(windows)
     }
-    Ok(windows)
-}
 
-/// Return a list of all windows.
-pub fn get_windows() -> Result<Vec<DesktopWindow>> {
-    // Establish a connection to i3 over a unix socket
-    let mut connection = I3Connection::connect().context("Couldn't acquire i3 connection")?;
-    let workspaces = connection
-        .get_workspaces()
-        .context("Problem communicating with i3")?
-        .workspaces;
-    let visible_workspaces = workspaces.iter().filter(|w| w.visible);
-    let root_node = connection.get_tree()?;
-    let mut windows = vec![];
-    for workspace in visible_workspaces {
-        windows.extend(crawl_windows(&root_node, workspace)?);
+    /// Focus a specific `window`.
+    fn focus_window(&self, window: &DesktopWindow) -> Result<()> {
+        let mut connection = I3Connection::connect().context("Couldn't acquire i3 connection")?;
+        let command_str = format!("[con_id=\"{}\"] focus", window.id);
+        let command = connection
+            .run_command(&command_str)
+            .context("Couldn't communicate with i3")?;
+        info!("Sending to i3: {:?}", command);
+        Ok(())
     }
-    Ok(windows)
-}
 
-/// Focus a specific `window`.
-pub fn focus_window(window: &DesktopWindow) -> Result<()> {
-    let mut connection = I3Connection::connect().context("Couldn't acquire i3 connection")?;
-    let command_str = format!("[con_id=\"{}\"] focus", window.id);
-    let command = connection
-        .run_command(&command_str)
-        .context("Couldn't communicate with i3")?;
-    info!("Sending to i3: {:?}", command);
-    Ok(())
-}
+    /// Swap `active_window` with `window`.
+    fn swap_windows(&self, active_window: &DesktopWindow, window: &DesktopWindow) -> Result<()> {
+        let mut connection = I3Connection::connect().context("Couldn't acquire i3 connection")?;
+        let command_str = format!(
+            "[con_id=\"{}\"] swap with container con_id {}",
+            active_window.id, window.id
+        );
+        let command = connection
+            .run_command(&command_str)
+            .context("Couldn't communicate with i3")?;
+        info!("Sending to i3: {:?}", command);
+        Ok(())
+    }
+
+    /// Return a list of all workspaces.
+    fn get_workspaces(&self) -> Result<Vec<DesktopWorkspace>> {
+        let mut connection = I3Connection::connect().context("Couldn't acquire i3 connection")?;
+        let workspaces = connection
+            .get_workspaces()
+            .context("Problem communicating with i3")?
+            .workspaces
+            .into_iter()
+            .map(|w| DesktopWorkspace {
+                id: i64::from(w.num),
+                name: w.name,
+                output: Some(w.output),
+                pos: (w.rect.0, w.rect.1),
+                size: (w.rect.2, w.rect.3),
+                is_focused: w.focused,
+                is_visible: w.visible,
+            })
+            .collect();
+        Ok(workspaces)
+    }
 
-/// Focus a specific `window`.
-pub fn swap_windows(active_window: &DesktopWindow, window: &DesktopWindow) -> Result<()> {
-    let mut connection = I3Connection::connect().context("Couldn't acquire i3 connection")?;
-    let command_str = format!(
-        "[con_id=\"{}\"] swap with container con_id {}",
-        active_window.id, window.id
-    );
-    let command = connection
-        .run_command(&command_str)
-        .context("Couldn't communicate with i3")?;
-    info!("Sending to i3: {:?}", command);
-    Ok(())
+    /// Switch to `workspace` by its name.
+    fn focus_workspace(&self, workspace: &DesktopWorkspace) -> Result<()> {
+        let mut connection = I3Connection::connect().context("Couldn't acquire i3 connection")?;
+        let command_str = format!("workspace {}", quote_command_arg(&workspace.name));
+        let command = connection
+            .run_command(&command_str)
+            .context("Couldn't communicate with i3")?;
+        info!("Sending to i3: {:?}", command);
+        Ok(())
+    }
+
+    /// Focus `output` by its name.
+    fn focus_output(&self, output: &DesktopOutput) -> Result<()> {
+        let mut connection = I3Connection::connect().context("Couldn't acquire i3 connection")?;
+        let command_str = format!("focus output {}", quote_command_arg(&output.name));
+        let command = connection
+            .run_command(&command_str)
+            .context("Couldn't communicate with i3")?;
+        info!("Sending to i3: {:?}", command);
+        Ok(())
+    }
+
+    /// Move `window` to `output`.
+    fn move_window_to_output(&self, window: &DesktopWindow, output: &DesktopOutput) -> Result<()> {
+        let mut connection = I3Connection::connect().context("Couldn't acquire i3 connection")?;
+        let command_str = format!(
+            "[con_id=\"{}\"] move container to output {}",
+            window.id,
+            quote_command_arg(&output.name)
+        );
+        let command = connection
+            .run_command(&command_str)
+            .context("Couldn't communicate with i3")?;
+        info!("Sending to i3: {:?}", command);
+        Ok(())
+    }
+
+    /// Describe a window by its X11 id, optionally followed by class, workspace, PID and title.
+    fn describe_window(&self, window: &DesktopWindow, details: bool) -> Result<String> {
+        let id = format!("0x{:x}", window.x_window_id.unwrap_or(0));
+        if !details {
+            return Ok(id);
+        }
+        let pid = match window.x_window_id {
+            Some(x_window_id) => {
+                window_pid(x_window_id).context("Couldn't get the window's PID")?
+            }
+            None => None,
+        };
+        Ok(window_details(&id, window, pid))
+    }
+
+    /// Return a list of all active outputs.
+    fn get_outputs(&self) -> Result<Vec<DesktopOutput>> {
+        let mut connection = I3Connection::connect().context("Couldn't acquire i3 connection")?;
+        let outputs = connection
+            .get_outputs()
+            .context("Problem communicating with i3")?
+            .outputs
+            .into_iter()
+            .filter(|o| o.active)
+            .map(|o| DesktopOutput {
+                name: o.name,
+                pos: (o.rect.0, o.rect.1),
+                size: (o.rect.2, o.rect.3),
+            })
+            .collect();
+        Ok(outputs)
+    }
+
+    /// Report focus and urgency changes and closed windows from i3's window events.
+    fn watch(&self, on_event: &mut dyn FnMut(WindowEvent) -> Result<()>) -> Result<()> {
+        let mut listener = I3EventListener::connect().context("Couldn't acquire i3 connection")?;
+        listener
+            .subscribe(&[Subscription::Window])
+            .context("Couldn't subscribe to i3 window events")?;
+        for event in listener.listen() {
+            let Event::WindowEvent(info) = event.context("Problem communicating with i3")? else {
+                continue;
+            };
+            match info.change {
+                WindowChange::Focus => on_event(WindowEvent::Focused(info.container.id))?,
+                WindowChange::Urgent => on_event(WindowEvent::Urgent(
+                    info.container.id,
+                    info.container.urgent,
+                ))?,
+                WindowChange::Close => on_event(WindowEvent::Closed(info.container.id))?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,635 @@
+use std::env;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+
+use anyhow::{bail, Context, Result};
+use log::{debug, info};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::wm::{quote_command_arg, window_details, WindowEvent, WindowManager};
+use crate::wm_tree::{self, TreeNode, TreeWindow};
+use crate::{DesktopOutput, DesktopWindow, DesktopWorkspace, HintKind};
+
+const IPC_MAGIC: &[u8] = b"i3-ipc";
+const IPC_RUN_COMMAND: u32 = 0;
+const IPC_GET_WORKSPACES: u32 = 1;
+const IPC_SUBSCRIBE: u32 = 2;
+const IPC_GET_OUTPUTS: u32 = 3;
+const IPC_GET_TREE: u32 = 4;
+/// Type of window events, which have the highest bit set to tell them from replies.
+const IPC_EVENT_WINDOW: u32 = 0x8000_0003;
+
+/// Sway rendered through a Wayland layer surface.
+pub struct Sway;
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+struct Rect {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+/// The subset of a sway tree node that we care about.
+#[derive(Deserialize, Debug)]
+struct Node {
+    id: i64,
+    name: Option<String>,
+    #[serde(rename = "type")]
+    node_type: String,
+    layout: Option<String>,
+    rect: Rect,
+    deco_rect: Rect,
+    #[serde(default)]
+    focused: bool,
+    /// Only set for Wayland-native views.
+    app_id: Option<String>,
+    /// Only set for Xwayland views.
+    window: Option<i32>,
+    /// Only set for views, either "xdg_shell" or "xwayland".
+    shell: Option<String>,
+    /// Only set for Xwayland views.
+    window_properties: Option<WindowProperties>,
+    /// Only set for views.
+    pid: Option<i32>,
+    /// 0 for none, 1 for workspace fullscreen and 2 for global fullscreen.
+    #[serde(default)]
+    fullscreen_mode: u8,
+    #[serde(default)]
+    urgent: bool,
+    /// Ids of the children, the most recently focused first.
+    #[serde(default)]
+    focus: Vec<i64>,
+    #[serde(default)]
+    nodes: Vec<Node>,
+    #[serde(default)]
+    floating_nodes: Vec<Node>,
+}
+
+#[derive(Deserialize, Debug)]
+struct WindowProperties {
+    class: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Workspace {
+    num: i64,
+    name: String,
+    focused: bool,
+    visible: bool,
+    output: String,
+    rect: Rect,
+}
+
+#[derive(Deserialize, Debug)]
+struct Output {
+    name: String,
+    active: bool,
+    rect: Rect,
+}
+
+#[derive(Deserialize, Debug)]
+struct CommandOutcome {
+    success: bool,
+    error: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SubscribeOutcome {
+    success: bool,
+}
+
+/// The subset of a window event that we care about.
+#[derive(Deserialize, Debug)]
+struct WindowChange {
+    change: String,
+    container: ChangedContainer,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChangedContainer {
+    id: i64,
+    #[serde(default)]
+    urgent: bool,
+}
+
+/// Turn the body of a window event into the change that `--track` records, if any.
+fn window_event(body: &[u8]) -> Result<Option<WindowEvent>> {
+    let change: WindowChange =
+        serde_json::from_slice(body).context("Couldn't parse window event from sway")?;
+    Ok(match change.change.as_str() {
+        "focus" => Some(WindowEvent::Focused(change.container.id)),
+        "urgent" => Some(WindowEvent::Urgent(
+            change.container.id,
+            change.container.urgent,
+        )),
+        "close" => Some(WindowEvent::Closed(change.container.id)),
+        _ => None,
+    })
+}
+
+/// A connection to sway's i3-compatible IPC socket.
+struct SwayConnection {
+    stream: UnixStream,
+}
+
+impl SwayConnection {
+    fn connect() -> Result<SwayConnection> {
+        let socket_path = env::var("SWAYSOCK").context("SWAYSOCK is not set")?;
+        let stream = UnixStream::connect(&socket_path)
+            .with_context(|| format!("Couldn't connect to sway socket at {socket_path}"))?;
+        Ok(SwayConnection { stream })
+    }
+
+    /// Send a message of type `message_type` and deserialize the reply.
+    fn request<T: DeserializeOwned>(&mut self, message_type: u32, payload: &str) -> Result<T> {
+        let mut message = Vec::with_capacity(IPC_MAGIC.len() + 8 + payload.len());
+        message.extend_from_slice(IPC_MAGIC);
+        message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+        message.extend_from_slice(&message_type.to_ne_bytes());
+        message.extend_from_slice(payload.as_bytes());
+        self.stream
+            .write_all(&message)
+            .context("Couldn't send message to sway")?;
+
+        let (_, body) = self.receive()?;
+        serde_json::from_slice(&body).context("Couldn't parse reply from sway")
+    }
+
+    /// Read the next reply or event and return its type with the body.
+    fn receive(&mut self) -> Result<(u32, Vec<u8>)> {
+        let mut header = [0u8; 14];
+        self.stream
+            .read_exact(&mut header)
+            .context("Couldn't read reply header from sway")?;
+        if &header[..6] != IPC_MAGIC {
+            bail!("Invalid reply from sway");
+        }
+        let length = u32::from_ne_bytes(header[6..10].try_into()?) as usize;
+        let message_type = u32::from_ne_bytes(header[10..14].try_into()?);
+        let mut body = vec![0u8; length];
+        self.stream
+            .read_exact(&mut body)
+            .context("Couldn't read reply from sway")?;
+        Ok((message_type, body))
+    }
+
+    fn run_command(&mut self, command: &str) -> Result<()> {
+        info!("Sending to sway: {:?}", command);
+        let outcomes: Vec<CommandOutcome> = self.request(IPC_RUN_COMMAND, command)?;
+        if let Some(failed) = outcomes.iter().find(|o| !o.success) {
+            bail!(
+                "sway couldn't run '{}': {}",
+                command,
+                failed.error.as_deref().unwrap_or("unknown error")
+            );
+        }
+        Ok(())
+    }
+}
+
+impl TreeNode for Node {
+    fn id(&self) -> i64 {
+        self.id
+    }
+
+    fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    fn floating_nodes(&self) -> &[Node] {
+        &self.floating_nodes
+    }
+
+    fn focus(&self) -> &[i64] {
+        &self.focus
+    }
+
+    fn is_window(&self) -> bool {
+        self.shell.is_some()
+    }
+}
+
+/// Return a list of all `DesktopWindow`s below `workspace_node`.
+///
+/// Both Wayland-native (`app_id`) and Xwayland (`window`) views are returned. `output` is the name
+/// of the output the workspace is on and `is_visible` tells whether it's currently shown there.
+fn crawl_windows(
+    root_node: &Node,
+    workspace_node: &Node,
+    output: Option<&str>,
+    is_visible: bool,
+) -> Vec<DesktopWindow> {
+    let mut windows = vec![];
+    for TreeWindow {
+        node,
+        parent,
+        is_floating,
+    } in wm_tree::crawl_windows(root_node, workspace_node)
+    {
+        // Unlike i3, sway's rect already excludes the title bar. Only tabbed and stacked
+        // containers need their hints moved up onto the title bar row.
+        let parent_layout = parent.map(|p| (p.rect, p.layout.as_deref().unwrap_or_default()));
+        let (pos, size) = match parent_layout {
+            Some((parent_rect, "tabbed")) => (
+                (
+                    parent_rect.x + node.deco_rect.x,
+                    parent_rect.y + node.deco_rect.y,
+                ),
+                (
+                    node.deco_rect.width,
+                    node.rect.height + node.deco_rect.height,
+                ),
+            ),
+            Some((parent_rect, "stacked")) => (
+                (node.rect.x, parent_rect.y + node.deco_rect.y),
+                (node.rect.width, node.rect.height + node.deco_rect.height),
+            ),
+            _ => (
+                (node.rect.x, node.rect.y),
+                (node.rect.width, node.rect.height),
+            ),
+        };
+
+        let window = DesktopWindow {
+            id: node.id,
+            x_window_id: node.window,
+            address: None,
+            pos,
+            size,
+            is_focused: node.focused,
+            title: node.name.clone(),
+            class: node.app_id.clone().or_else(|| {
+                node.window_properties
+                    .as_ref()
+                    .and_then(|p| p.class.clone())
+            }),
+            workspace: workspace_node.name.clone(),
+            output: output.map(str::to_string),
+            is_floating,
+            is_fullscreen: node.fullscreen_mode != 0,
+            is_urgent: node.urgent,
+            urgency_history: None,
+            is_visible,
+            focus_history: None,
+            kind: HintKind::Window,
+        };
+        debug!("Found {:?}", window);
+        windows.push(window);
+    }
+    windows
+}
+
+/// Find all workspace nodes in the tree below `root_node`.
+fn find_workspace_nodes(root_node: &Node) -> Vec<&Node> {
+    let mut workspaces = vec![];
+    let mut nodes_to_explore: Vec<&Node> = root_node.nodes.iter().collect();
+    while !nodes_to_explore.is_empty() {
+        let mut next_vec = vec![];
+        for node in &nodes_to_explore {
+            if node.node_type == "workspace" {
+                workspaces.push(*node);
+            } else {
+                next_vec.extend(node.nodes.iter());
+            }
+        }
+        nodes_to_explore = next_vec;
+    }
+    workspaces
+}
+
+impl WindowManager for Sway {
+    /// Return a list of all windows on visible or, with `all_workspaces`, all workspaces.
+    ///
+    /// This includes the scratchpad which sway keeps as a hidden workspace in the tree.
+    fn get_windows(&self, all_workspaces: bool) -> Result<Vec<DesktopWindow>> {
+        let mut connection = SwayConnection::connect()?;
+        let workspaces: Vec<Workspace> = connection
+            .request(IPC_GET_WORKSPACES, "")
+            .context("Problem communicating with sway")?;
+        let root_node: Node = connection
+            .request(IPC_GET_TREE, "")
+            .context("Problem communicating with sway")?;
+
+        let mut windows = vec![];
+        for workspace_node in find_workspace_nodes(&root_node) {
+            let workspace = workspaces
+                .iter()
+                .find(|w| workspace_node.name.as_ref() == Some(&w.name));
+            let is_visible = workspace.is_some_and(|w| w.visible);
+            if is_visible || all_workspaces {
+                windows.extend(crawl_windows(
+                    &root_node,
+                    workspace_node,
+                    workspace.map(|w| w.output.as_str()),
+                    is_visible,
+                ));
+            }
+        }
+
+        let focus_history = wm_tree::focus_history(&root_node);
+        for window in &mut windows {
+            window.focus_history = focus_history.iter().position(|id| *id == window.id);
+        }
+        Ok(windows)
+    }
+
+    /// Focus a specific `window`.
+    fn focus_window(&self, window: &DesktopWindow) -> Result<()> {
+        let mut connection = SwayConnection::connect()?;
+        connection.run_command(&format!("[con_id={}] focus", window.id))
+    }
+
+    /// Swap `active_window` with `window`.
+    fn swap_windows(&self, active_window: &DesktopWindow, window: &DesktopWindow) -> Result<()> {
+        let mut connection = SwayConnection::connect()?;
+        connection.run_command(&format!(
+            "[con_id={}] swap container with con_id {}",
+            active_window.id, window.id
+        ))
+    }
+
+    /// Return a list of all workspaces.
+    fn get_workspaces(&self) -> Result<Vec<DesktopWorkspace>> {
+        let mut connection = SwayConnection::connect()?;
+        let workspaces: Vec<Workspace> = connection
+            .request(IPC_GET_WORKSPACES, "")
+            .context("Problem communicating with sway")?;
+        Ok(workspaces
+            .into_iter()
+            .map(|w| DesktopWorkspace {
+                id: w.num,
+                name: w.name,
+                output: Some(w.output),
+                pos: (w.rect.x, w.rect.y),
+                size: (w.rect.width, w.rect.height),
+                is_focused: w.focused,
+                is_visible: w.visible,
+            })
+            .collect())
+    }
+
+    /// Switch to `workspace` by its name.
+    fn focus_workspace(&self, workspace: &DesktopWorkspace) -> Result<()> {
+        let mut connection = SwayConnection::connect()?;
+        connection.run_command(&format!("workspace {}", quote_command_arg(&workspace.name)))
+    }
+
+    /// Focus `output` by its name.
+    fn focus_output(&self, output: &DesktopOutput) -> Result<()> {
+        let mut connection = SwayConnection::connect()?;
+        connection.run_command(&format!("focus output {}", quote_command_arg(&output.name)))
+    }
+
+    /// Move `window` to `output`.
+    fn move_window_to_output(&self, window: &DesktopWindow, output: &DesktopOutput) -> Result<()> {
+        let mut connection = SwayConnection::connect()?;
+        connection.run_command(&format!(
+            "[con_id={}] move container to output {}",
+            window.id,
+            quote_command_arg(&output.name)
+        ))
+    }
+
+    /// Describe a window by its con_id, optionally followed by class, workspace, PID and title.
+    ///
+    /// Unlike the X11 window id, the con_id exists for Wayland-native views as well and it's what
+    /// `swaymsg '[con_id=...]'` takes.
+    fn describe_window(&self, window: &DesktopWindow, details: bool) -> Result<String> {
+        let id = window.id.to_string();
+        if !details {
+            return Ok(id);
+        }
+
+        let mut connection = SwayConnection::connect()?;
+        let root_node: Node = connection
+            .request(IPC_GET_TREE, "")
+            .context("Problem communicating with sway")?;
+        let pid = wm_tree::find_node(&root_node, window.id).and_then(|n| n.pid);
+        Ok(window_details(&id, window, pid))
+    }
+
+    /// Return a list of all active outputs.
+    fn get_outputs(&self) -> Result<Vec<DesktopOutput>> {
+        let mut connection = SwayConnection::connect()?;
+        let outputs: Vec<Output> = connection
+            .request(IPC_GET_OUTPUTS, "")
+            .context("Problem communicating with sway")?;
+        Ok(outputs
+            .into_iter()
+            .filter(|o| o.active)
+            .map(|o| DesktopOutput {
+                name: o.name,
+                pos: (o.rect.x, o.rect.y),
+                size: (o.rect.width, o.rect.height),
+            })
+            .collect())
+    }
+
+    /// Report focus and urgency changes and closed windows from sway's window events.
+    fn watch(&self, on_event: &mut dyn FnMut(WindowEvent) -> Result<()>) -> Result<()> {
+        let mut connection = SwayConnection::connect()?;
+        let outcome: SubscribeOutcome = connection
+            .request(IPC_SUBSCRIBE, r#"["window"]"#)
+            .context("Problem communicating with sway")?;
+        if !outcome.success {
+            bail!("sway refused the subscription to window events");
+        }
+        loop {
+            let (message_type, body) = connection.receive()?;
+            if message_type != IPC_EVENT_WINDOW {
+                continue;
+            }
+            if let Some(event) = window_event(&body)? {
+                on_event(event)?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::History;
+
+    fn view(id: i64, rect: (i32, i32, i32, i32), app_id: Option<&str>) -> String {
+        format!(
+            r#"{{"id": {id}, "name": "view {id}", "type": "con", "layout": "none",
+                "rect": {{"x": {}, "y": {}, "width": {}, "height": {}}},
+                "deco_rect": {{"x": 0, "y": 0, "width": 0, "height": 0}},
+                "focused": false, "app_id": {}, "window": {}, "shell": "{}",
+                "nodes": [], "floating_nodes": []}}"#,
+            rect.0,
+            rect.1,
+            rect.2,
+            rect.3,
+            app_id.map_or("null".to_string(), |a| format!("\"{a}\"")),
+            if app_id.is_some() { "null" } else { "4711" },
+            if app_id.is_some() {
+                "xdg_shell"
+            } else {
+                "xwayland"
+            },
+        )
+    }
+
+    fn tree(layout: &str, views: &[String]) -> Node {
+        let json = format!(
+            r#"{{"id": 1, "name": "root", "type": "root", "layout": "splith",
+                "rect": {{"x": 0, "y": 0, "width": 1920, "height": 1080}},
+                "deco_rect": {{"x": 0, "y": 0, "width": 0, "height": 0}},
+                "nodes": [{{"id": 2, "name": "1", "type": "workspace", "layout": "{layout}",
+                    "rect": {{"x": 0, "y": 0, "width": 1920, "height": 1080}},
+                    "deco_rect": {{"x": 0, "y": 0, "width": 0, "height": 0}},
+                    "nodes": [{}], "floating_nodes": []}}]}}"#,
+            views.join(",")
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn test_crawl_native_and_xwayland_views() {
+        let root = tree(
+            "splith",
+            &[
+                view(10, (0, 0, 960, 1080), Some("foot")),
+                view(11, (960, 0, 960, 1080), None),
+            ],
+        );
+        let workspaces = find_workspace_nodes(&root);
+        assert_eq!(workspaces.len(), 1);
+
+        let windows = crawl_windows(&root, workspaces[0], Some("eDP-1"), true);
+        assert_eq!(windows.len(), 2);
+        assert_eq!(windows[0].id, 10);
+        assert_eq!(windows[0].x_window_id, None);
+        assert_eq!(windows[0].class.as_deref(), Some("foot"));
+        assert_eq!(windows[0].title.as_deref(), Some("view 10"));
+        assert_eq!(windows[0].workspace.as_deref(), Some("1"));
+        assert_eq!(windows[0].output.as_deref(), Some("eDP-1"));
+        assert!(!windows[0].is_floating);
+        assert_eq!(windows[1].id, 11);
+        assert_eq!(windows[1].x_window_id, Some(4711));
+        assert_eq!(windows[1].pos, (960, 0));
+        assert_eq!(windows[1].size, (960, 1080));
+    }
+
+    #[test]
+    fn test_crawl_floating_xwayland_view() {
+        let mut root = tree(
+            "splith",
+            &[
+                view(10, (0, 0, 1920, 1080), Some("foot")),
+                view(11, (100, 100, 400, 300), None),
+            ],
+        );
+        let mut floating = root.nodes[0].nodes.pop().unwrap();
+        floating.window_properties = Some(WindowProperties {
+            class: Some("Steam".to_string()),
+        });
+        root.nodes[0].floating_nodes.push(floating);
+        root.nodes[0].nodes[0].fullscreen_mode = 1;
+        root.nodes[0].floating_nodes[0].urgent = true;
+
+        let workspaces = find_workspace_nodes(&root);
+        let windows = crawl_windows(&root, workspaces[0], Some("eDP-1"), true);
+        assert_eq!(windows.len(), 2);
+        assert!(windows[0].is_fullscreen);
+        assert!(!windows[0].is_floating);
+        assert!(!windows[0].is_urgent);
+        assert_eq!(windows[1].class.as_deref(), Some("Steam"));
+        assert!(windows[1].is_floating);
+        assert!(!windows[1].is_fullscreen);
+        assert!(windows[1].is_urgent);
+    }
+
+    #[test]
+    fn test_crawl_tabbed_views_use_title_bar() {
+        let mut root = tree(
+            "tabbed",
+            &[
+                view(10, (0, 20, 1920, 1060), Some("foot")),
+                view(11, (0, 20, 1920, 1060), Some("firefox")),
+            ],
+        );
+        root.nodes[0].nodes[1].deco_rect = Rect {
+            x: 960,
+            y: 0,
+            width: 960,
+            height: 20,
+        };
+        let workspaces = find_workspace_nodes(&root);
+        let windows = crawl_windows(&root, workspaces[0], Some("eDP-1"), true);
+        assert_eq!(windows[1].pos, (960, 0));
+        assert_eq!(windows[1].size, (960, 1080));
+    }
+
+    #[test]
+    fn test_focus_history_follows_focus_lists() {
+        let mut root = tree(
+            "splith",
+            &[
+                view(10, (0, 0, 960, 1080), Some("foot")),
+                view(11, (960, 0, 960, 1080), Some("firefox")),
+            ],
+        );
+        let mut floating = root.nodes[0].nodes.pop().unwrap();
+        floating.id = 12;
+        root.nodes[0].floating_nodes.push(floating);
+        root.nodes[0].focus = vec![12, 10];
+        root.focus = vec![2];
+        assert_eq!(wm_tree::focus_history(&root), [12, 10]);
+
+        root.nodes[0].focus = vec![10, 12];
+        assert_eq!(wm_tree::focus_history(&root), [10, 12]);
+    }
+
+    #[test]
+    fn test_tracked_focus_history_across_containers() {
+        // A split container with views 10 and 11 next to view 12. The user focused 10, then 12
+        // and then 11.
+        let mut root = tree(
+            "splith",
+            &[
+                view(10, (0, 0, 960, 540), Some("foot")),
+                view(11, (0, 540, 960, 540), Some("foot")),
+                view(12, (960, 0, 960, 1080), Some("firefox")),
+            ],
+        );
+        let single = root.nodes[0].nodes.pop().unwrap();
+        let mut container: Node =
+            serde_json::from_str(&view(20, (0, 0, 960, 1080), Some("none"))).unwrap();
+        container.shell = None;
+        container.app_id = None;
+        container.layout = Some("splitv".to_string());
+        container.nodes = root.nodes[0].nodes.drain(..).collect();
+        container.nodes[1].focused = true;
+        container.focus = vec![11, 10];
+        root.nodes[0].nodes = vec![container, single];
+        root.nodes[0].focus = vec![20, 12];
+        root.focus = vec![2];
+
+        // The focus lists alone put 10 before 12 as it's in the container focused last.
+        let focus_history = wm_tree::focus_history(&root);
+        assert_eq!(focus_history, [11, 10, 12]);
+
+        let workspaces = find_workspace_nodes(&root);
+        let mut windows = crawl_windows(&root, workspaces[0], Some("eDP-1"), true);
+        for window in &mut windows {
+            window.focus_history = focus_history.iter().position(|id| *id == window.id);
+        }
+        let mut history = History::default();
+        for id in [10, 12, 11] {
+            let body = format!(
+                r#"{{"change": "focus", "container": {}}}"#,
+                view(id, (0, 0, 960, 1080), Some("foot"))
+            );
+            history.record(window_event(body.as_bytes()).unwrap().unwrap());
+        }
+        history.apply(&mut windows);
+
+        let previous = windows.iter().find(|w| w.focus_history == Some(1)).unwrap();
+        assert_eq!(previous.id, 12);
+    }
+}
@@ -0,0 +1,104 @@
+//! Walking the container trees of i3 and sway, which are laid out the same way.
+
+/// A node in the container tree of i3 or sway.
+pub trait TreeNode: Sized {
+    fn id(&self) -> i64;
+
+    /// Tiled children.
+    fn nodes(&self) -> &[Self];
+
+    /// Floating children.
+    fn floating_nodes(&self) -> &[Self];
+
+    /// Ids of the children, the most recently focused first.
+    fn focus(&self) -> &[i64];
+
+    /// Whether the node is a window rather than a container.
+    fn is_window(&self) -> bool;
+
+    /// Tiled children followed by floating ones.
+    fn children(&self) -> impl Iterator<Item = &Self> {
+        self.nodes().iter().chain(self.floating_nodes())
+    }
+}
+
+/// A window node found by `crawl_windows`.
+#[derive(Debug)]
+pub struct TreeWindow<'a, N> {
+    pub node: &'a N,
+    /// The container holding the window, which decides how its title bar is laid out.
+    pub parent: Option<&'a N>,
+    /// Whether the window lives below a floating container.
+    pub is_floating: bool,
+}
+
+/// Find the node with `id`.
+#[cfg(feature = "sway")]
+pub fn find_node<N: TreeNode>(start_node: &N, id: i64) -> Option<&N> {
+    if start_node.id() == id {
+        return Some(start_node);
+    }
+    start_node.children().find_map(|n| find_node(n, id))
+}
+
+/// Find parent of the node with `child_id`.
+pub fn find_parent_of<N: TreeNode>(start_node: &N, child_id: i64) -> Option<&N> {
+    let mut nodes_to_explore: Vec<&N> = vec![start_node];
+    while !nodes_to_explore.is_empty() {
+        let mut next_vec = vec![];
+        for node in &nodes_to_explore {
+            if node.children().any(|x| x.id() == child_id) {
+                return Some(node);
+            }
+            next_vec.extend(node.children());
+        }
+        nodes_to_explore = next_vec;
+    }
+    None
+}
+
+/// Return all windows below `workspace_node`, breadth first.
+pub fn crawl_windows<'a, N: TreeNode>(
+    root_node: &'a N,
+    workspace_node: &'a N,
+) -> Vec<TreeWindow<'a, N>> {
+    // Every node is paired with whether it lives below a floating container.
+    let mut nodes_to_explore: Vec<(&N, bool)> =
+        workspace_node.nodes().iter().map(|n| (n, false)).collect();
+    nodes_to_explore.extend(workspace_node.floating_nodes().iter().map(|n| (n, true)));
+    let mut windows = vec![];
+    while !nodes_to_explore.is_empty() {
+        let mut next_vec = vec![];
+        for &(node, is_floating) in &nodes_to_explore {
+            next_vec.extend(node.nodes().iter().map(|n| (n, is_floating)));
+            next_vec.extend(node.floating_nodes().iter().map(|n| (n, true)));
+            if node.is_window() {
+                windows.push(TreeWindow {
+                    node,
+                    parent: find_parent_of(root_node, node.id()),
+                    is_floating,
+                });
+            }
+        }
+        nodes_to_explore = next_vec;
+    }
+    windows
+}
+
+/// Ids of all windows below `node`, roughly the most recently focused first.
+///
+/// Every container lists its children in the order they were focused, which we follow depth-first.
+/// This only orders the windows within each container, all windows of a container come before
+/// those of a container that was focused before it. If the user focused A, then C and then B in a
+/// container holding A and B next to C, this returns B, A, C although C was focused after A.
+/// `wmfocus --track` records the actual order.
+pub fn focus_history<N: TreeNode>(node: &N) -> Vec<i64> {
+    if node.is_window() {
+        return vec![node.id()];
+    }
+    node.focus()
+        .iter()
+        .filter_map(|id| node.children().find(|n| n.id() == *id))
+        .flat_map(focus_history)
+        .collect()
+}